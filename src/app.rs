@@ -0,0 +1,918 @@
+use crate::canvas::Canvas;
+use crate::config::{Brush, CanvasSource, StartOptions, TilesetSource};
+use crate::error::Error;
+use crate::events::{fire, EventHandlers};
+use crate::highlight::{Highlight, Highlights};
+use crate::on_load_image;
+use crate::renderer::{create_renderer, render, DisplayMode, Renderer};
+use crate::replay::{records_from_js, records_to_js};
+use crate::tileset::Tileset;
+use crate::transition::{Transition, TransitionStyle};
+use crate::update::{Context, Status};
+use crate::utils::set_panic_hook;
+use crate::view::{to_square, View};
+use crate::worker;
+use js_sys::{Array, Date, Function, Reflect};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{console, window};
+use web_sys::{
+    Element, HtmlCanvasElement, HtmlImageElement, MessageEvent, MouseEvent, WheelEvent, Worker,
+};
+
+#[wasm_bindgen]
+extern "C" {
+    // Global rather than on `Window`, workers rendering to an `OffscreenCanvas`
+    // have them too.
+    #[wasm_bindgen(js_name = requestAnimationFrame)]
+    fn request_animation_frame(callback: &Function) -> i32;
+    #[wasm_bindgen(js_name = cancelAnimationFrame)]
+    fn cancel_animation_frame(id: i32);
+
+    // Not in the web-sys we build against without its unstable APIs.
+    type ResizeObserver;
+    #[wasm_bindgen(constructor, catch)]
+    fn new(callback: &Function) -> Result<ResizeObserver, JsValue>;
+    #[wasm_bindgen(method)]
+    fn observe(this: &ResizeObserver, target: &Element);
+    #[wasm_bindgen(method)]
+    fn disconnect(this: &ResizeObserver);
+}
+
+// Further than this many canvas pixels between pressing and letting go of the
+// button, it was a drag rather than a click.
+const CLICK_DISTANCE: f64 = 4.;
+
+type FrameCallback = Rc<RefCell<Option<Closure<dyn FnMut()>>>>;
+type ResizeCallback = (ResizeObserver, Closure<dyn FnMut(Array)>);
+type MouseCallback = (&'static str, Closure<dyn FnMut(MouseEvent)>);
+// What a mouse event does to the state, given where it is in canvas pixels.
+type MouseHandler = fn(&mut AppState, &MouseEvent, (f64, f64));
+
+struct AppState {
+    canvas: Canvas,
+    renderer: Box<dyn Renderer>,
+    display_mode: DisplayMode,
+    entropy_overlay: bool,
+    view: View,
+    pixel_art: bool,
+    // Hovered and selected cells, the grid and colors. The solver's cells
+    // are filled in on every render.
+    highlights: Highlights,
+    transition: Transition,
+    // Where the mouse was last while dragging the view, in canvas pixels.
+    drag: Option<(f64, f64)>,
+    // Where the button went down, to tell clicks from drags.
+    press: Option<(f64, f64)>,
+    brush: Brush,
+    context: Context,
+    start_time: f64,
+    // What the renderer's clock counts from, kept small so that it fits the
+    // renderers' single precision collapse times.
+    clock_start: f64,
+    steps_per_frame: u32,
+    paused: bool,
+    frame_id: Option<i32>,
+    handlers: EventHandlers,
+    // When set the solving happens in this worker, and `context` is only a
+    // copy of its map kept up to date from its messages.
+    worker: Option<Worker>,
+    worker_ready: bool,
+    worker_started: bool,
+    // Cells the worker wrote since the last render.
+    streamed: Vec<usize>,
+}
+
+// One generator drawing into one canvas. Everything it registers with the
+// page (animation frames, the resize observer, the tileset onload) is owned
+// here and torn down by `destroy`, so several can live side by side.
+#[wasm_bindgen]
+pub struct WfcApp {
+    state: Rc<RefCell<AppState>>,
+    frame_callback: FrameCallback,
+    resize_callback: Option<ResizeCallback>,
+    image: Option<HtmlImageElement>,
+    load_callback: Option<Closure<dyn FnMut() -> Result<(), JsValue>>>,
+    worker_callback: Option<Closure<dyn FnMut(MessageEvent)>>,
+    view_callbacks: Vec<MouseCallback>,
+}
+
+#[wasm_bindgen]
+impl WfcApp {
+    #[wasm_bindgen(constructor)]
+    pub fn new(options: JsValue) -> Result<WfcApp, JsValue> {
+        set_panic_hook();
+        let options = StartOptions::from_js(&options)?;
+        let canvas = match options.canvas {
+            CanvasSource::Canvas(canvas) => canvas,
+            CanvasSource::Id(id) => Canvas::Html(
+                window()
+                    .and_then(|window| window.document())
+                    .and_then(|document| document.get_element_by_id(&id))
+                    .ok_or_else(|| Error::MissingCanvas(format!("no element with id `{}`", id)))?
+                    .dyn_into::<HtmlCanvasElement>()
+                    .map_err(|_| Error::MissingCanvas(format!("`{}` is not a canvas", id)))?,
+            ),
+        };
+
+        let config = options.config;
+        let mut context = Context::new(config.width, config.height);
+        context.max_backtracks = config.max_backtracks;
+        context.set_seed(config.seed as u64);
+        let mut renderer = create_renderer(&canvas, options.renderer)?;
+        renderer.set_display_mode(options.display);
+        renderer.set_entropy_overlay(options.entropy_overlay);
+        renderer.set_pixel_art(options.pixel_art);
+        renderer.set_transition(options.transition);
+
+        let state = Rc::new(RefCell::new(AppState {
+            canvas,
+            renderer,
+            display_mode: options.display,
+            entropy_overlay: options.entropy_overlay,
+            view: View::default(),
+            pixel_art: options.pixel_art,
+            highlights: options.highlights,
+            transition: options.transition,
+            drag: None,
+            press: None,
+            brush: options.brush,
+            context,
+            start_time: Date::new_0().get_time(),
+            clock_start: Date::now(),
+            steps_per_frame: options.steps_per_frame,
+            paused: false,
+            frame_id: None,
+            handlers: EventHandlers::default(),
+            worker: options.worker,
+            worker_ready: false,
+            worker_started: false,
+            streamed: vec![],
+        }));
+        let mut app = WfcApp {
+            state,
+            frame_callback: Rc::new(RefCell::new(None)),
+            resize_callback: None,
+            image: None,
+            load_callback: None,
+            worker_callback: None,
+            view_callbacks: vec![],
+        };
+
+        let html_canvas = match &app.state.borrow().canvas {
+            Canvas::Html(canvas) => Some(canvas.clone()),
+            _ => None,
+        };
+        if let Some(canvas) = html_canvas {
+            resize_canvas(&canvas, device_pixel_size(&JsValue::UNDEFINED, &canvas));
+            if options.fit_canvas {
+                app.fit_canvas(&canvas)?;
+            }
+        }
+
+        let html_canvas = match &app.state.borrow().canvas {
+            Canvas::Html(canvas) if options.view_controls => Some(canvas.clone()),
+            _ => None,
+        };
+        if let Some(canvas) = html_canvas {
+            app.add_view_controls(&canvas)?;
+        }
+
+        let worker = app.state.borrow().worker.clone();
+        if let Some(worker) = worker {
+            let state = app.state.clone();
+            let closure = Closure::<dyn FnMut(MessageEvent)>::new(move |message: MessageEvent| {
+                let mut state = state.borrow_mut();
+                let state = &mut *state;
+                let result =
+                    worker::receive(&mut state.context, &message.data(), &mut state.streamed)
+                        .and_then(|ready| {
+                            state.worker_ready |= ready;
+                            state.start_worker()
+                        });
+                state.redraw();
+                if let Err(error) = result {
+                    console::error_1(&error);
+                }
+            });
+            worker.add_event_listener_with_callback("message", closure.as_ref().unchecked_ref())?;
+            app.worker_callback = Some(closure);
+        }
+
+        match options.tileset {
+            TilesetSource::Image(image) => {
+                let mut state = app.state.borrow_mut();
+                let state = &mut *state;
+                state.set_tileset(Tileset::from_image_data(&image)?)?;
+                state.start_worker()?;
+            }
+            TilesetSource::Url(url) => {
+                if window().is_none() {
+                    return Err(Error::invalid(
+                        "loading a tileset from a url needs a document, pass an ImageData instead",
+                    )
+                    .into());
+                }
+                let image = HtmlImageElement::new()?;
+                // Lets tilesets hosted on another origin be read back with
+                // `get_image_data` as long as that host sends CORS headers.
+                image.set_cross_origin(Some("anonymous"));
+                image.set_src(&url);
+                let state = app.state.clone();
+                let new_image = image.clone();
+                let closure = Closure::<dyn FnMut() -> Result<(), JsValue>>::new(move || {
+                    let result = on_load_image(&new_image)?;
+                    let mut state = state.borrow_mut();
+                    let state = &mut *state;
+                    state.set_tileset(Tileset::from_image_data(&result)?)?;
+                    state.start_worker()
+                });
+                image.set_onload(Some(closure.as_ref().unchecked_ref()));
+                app.image = Some(image);
+                app.load_callback = Some(closure);
+            }
+        }
+
+        {
+            let state = app.state.clone();
+            let callback = app.frame_callback.clone();
+            *app.frame_callback.borrow_mut() = Some(Closure::<dyn FnMut()>::new(move || {
+                let calls = {
+                    let mut state = state.borrow_mut();
+                    state.frame_id = None;
+                    if state.paused {
+                        return;
+                    }
+                    let drawn = state.draw_frame();
+                    state.frame_id = Some(Self::request_frame(&callback));
+                    drawn
+                        .map_err(JsValue::from)
+                        .and_then(|()| state.take_calls())
+                };
+                if let Err(error) = calls.and_then(fire) {
+                    console::error_1(&error);
+                }
+            }));
+        }
+        let frame_id = Self::request_frame(&app.frame_callback);
+        app.state.borrow_mut().frame_id = Some(frame_id);
+        Ok(app)
+    }
+
+    pub fn pause(&mut self) {
+        let mut state = self.state.borrow_mut();
+        state.paused = true;
+        state.post_to_worker("pause", None);
+        if let Some(frame_id) = state.frame_id.take() {
+            cancel_animation_frame(frame_id);
+        }
+    }
+
+    pub fn resume(&mut self) {
+        let mut state = self.state.borrow_mut();
+        state.paused = false;
+        state.post_to_worker("resume", None);
+        if state.frame_id.is_none() && self.frame_callback.borrow().is_some() {
+            state.frame_id = Some(Self::request_frame(&self.frame_callback));
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn paused(&self) -> bool {
+        self.state.borrow().paused
+    }
+
+    // Advances the solver by a single step and draws it, meant to be used
+    // while paused.
+    pub fn step(&mut self) -> Result<(), JsValue> {
+        let calls = {
+            let mut state = self.state.borrow_mut();
+            let state = &mut *state;
+            let changed_pixel = match state.worker {
+                Some(_) => {
+                    state.post_to_worker("step", None);
+                    None
+                }
+                None => state.context.step(),
+            };
+            state.render(changed_pixel.as_slice())?;
+            state.take_calls()?
+        };
+        fire(calls)
+    }
+
+    // Starts over from an empty map, with a new seed if one is given.
+    pub fn restart(&mut self, seed: Option<u32>) -> Result<(), JsValue> {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        match seed {
+            Some(seed) => state.context.set_seed(seed as u64),
+            None => state.context.reset(),
+        }
+        state.start_time = Date::new_0().get_time();
+        state.post_to_worker("restart", seed);
+        state.render(&[])?;
+        Ok(())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn seed(&self) -> u32 {
+        self.state.borrow().context.seed as u32
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn steps_per_frame(&self) -> u32 {
+        self.state.borrow().steps_per_frame
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_steps_per_frame(&mut self, steps_per_frame: u32) {
+        self.state.borrow_mut().steps_per_frame = steps_per_frame;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn status(&self) -> Status {
+        self.state.borrow().context.status
+    }
+
+    // The map as shown, as a PNG with `scale` pixels per cell.
+    pub fn to_png(&self, scale: Option<u32>) -> Result<Vec<u8>, JsValue> {
+        self.state
+            .borrow()
+            .context
+            .to_png(scale.map(|scale| scale as usize))
+            .map_err(JsValue::from)
+    }
+
+    // The map as shown, as JSON or CSV tile ids. See `WfcGenerator::to_json`.
+    pub fn to_json(&self) -> String {
+        self.state.borrow().context.to_json()
+    }
+
+    pub fn to_csv(&self) -> String {
+        self.state.borrow().context.to_csv()
+    }
+
+    // Solver snapshots as with `WfcGenerator`. Not available when solving in a
+    // worker, the page only has a copy of the map then.
+    pub fn snapshot(&self) -> Result<Vec<u8>, JsValue> {
+        let state = self.state.borrow();
+        if state.worker.is_some() {
+            return Err(Error::invalid("snapshots are not available with a worker").into());
+        }
+        Ok(state.context.snapshot())
+    }
+
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), JsValue> {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        if state.worker.is_some() {
+            return Err(Error::invalid("snapshots are not available with a worker").into());
+        }
+        state.context.restore(snapshot)?;
+        Ok(())
+    }
+
+    // Decision logs as with `WfcGenerator`, with the same worker caveat.
+    pub fn decision_log(&self) -> Result<Array, JsValue> {
+        let state = self.state.borrow();
+        if state.worker.is_some() {
+            return Err(Error::invalid("decision logs are not available with a worker").into());
+        }
+        records_to_js(&state.context.decision_log)
+    }
+
+    pub fn replay(&mut self, log: JsValue) -> Result<(), JsValue> {
+        let records = records_from_js(&log)?;
+        let mut state = self.state.borrow_mut();
+        if state.worker.is_some() {
+            return Err(Error::invalid("decision logs are not available with a worker").into());
+        }
+        state.context.start_replay(records);
+        Ok(())
+    }
+
+    // How undecided cells are drawn: `plain`, `superposition` (the rotations
+    // still possible) or `domain_size` (a heatmap of how many are left).
+    #[wasm_bindgen(getter)]
+    pub fn display_mode(&self) -> String {
+        self.state.borrow().display_mode.name().to_string()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_display_mode(&mut self, mode: &str) -> Result<(), JsValue> {
+        let mode = DisplayMode::from_name(mode)?;
+        let mut state = self.state.borrow_mut();
+        state.display_mode = mode;
+        state.renderer.set_display_mode(mode);
+        state.post_display()?;
+        state.redraw();
+        Ok(())
+    }
+
+    // Tints undecided cells from red, about to be decided, to blue, wide
+    // open, see `entropy_legend` for the colors.
+    #[wasm_bindgen(getter)]
+    pub fn entropy_overlay(&self) -> bool {
+        self.state.borrow().entropy_overlay
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_entropy_overlay(&mut self, enabled: bool) -> Result<(), JsValue> {
+        let mut state = self.state.borrow_mut();
+        state.entropy_overlay = enabled;
+        state.renderer.set_entropy_overlay(enabled);
+        state.post_display()?;
+        state.redraw();
+        Ok(())
+    }
+
+    // How far the view is zoomed in, 1 showing the whole map.
+    #[wasm_bindgen(getter)]
+    pub fn zoom(&self) -> f64 {
+        self.state.borrow().view.zoom
+    }
+
+    pub fn reset_view(&mut self) {
+        self.state.borrow_mut().set_view(View::default());
+    }
+
+    // Lines between the cells, colored by `highlight_colors.grid`.
+    #[wasm_bindgen(getter)]
+    pub fn grid(&self) -> bool {
+        self.state.borrow().highlights.grid
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_grid(&mut self, enabled: bool) {
+        let mut state = self.state.borrow_mut();
+        state.highlights.grid = enabled;
+        state.redraw();
+    }
+
+    // `{ grid, contradiction, selected, hovered, last_collapsed }` as hex
+    // colors, "#rrggbbaa" for see through ones. Left out keys stay as they are.
+    pub fn set_highlight_colors(&mut self, colors: JsValue) -> Result<(), JsValue> {
+        let mut state = self.state.borrow_mut();
+        state.highlights.set_colors(&colors)?;
+        state.redraw();
+        Ok(())
+    }
+
+    // Outlines the cell at `x`, `y` until `clear_selection`.
+    pub fn select_cell(&mut self, x: u32, y: u32) -> Result<(), JsValue> {
+        let mut state = self.state.borrow_mut();
+        let (width, height) = (state.context.map_width, state.context.map_height);
+        if x as usize >= width || y as usize >= height {
+            return Err(Error::invalid("cell out of the map").into());
+        }
+        state.set_highlight(Highlight::Selected, Some((x as usize, y as usize)));
+        Ok(())
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.state
+            .borrow_mut()
+            .set_highlight(Highlight::Selected, None);
+    }
+
+    // `[x, y]` of the cell under the mouse, if any.
+    #[wasm_bindgen(getter)]
+    pub fn hovered_cell(&self) -> Option<Vec<u32>> {
+        let state = self.state.borrow();
+        let width = state.context.map_width;
+        state
+            .highlights
+            .get(Highlight::Hovered)
+            .map(|cell| vec![(cell % width) as u32, (cell / width) as u32])
+    }
+
+    // Fixes the cell at `x`, `y` to `rotation`, or leaves it to the solver
+    // again without one, keeping as much of the map as still fits. Pins hold
+    // through `restart` until `clear_pins`.
+    pub fn pin(&mut self, x: u32, y: u32, rotation: Option<u32>) -> Result<(), JsValue> {
+        let calls = {
+            let mut state = self.state.borrow_mut();
+            let (x, y, rotation) = (x as usize, y as usize, rotation.map(|r| r as usize));
+            state.context.check_pin(x, y, rotation)?;
+            state.pin(x, y, rotation);
+            state.take_calls()?
+        };
+        fire(calls)
+    }
+
+    pub fn clear_pins(&mut self) -> Result<(), JsValue> {
+        let calls = {
+            let mut state = self.state.borrow_mut();
+            match state.worker {
+                Some(_) => {
+                    state.context.pins.fill(0);
+                    state.post_to_worker("clear_pins", None);
+                }
+                None => state.context.clear_pins(),
+            }
+            state.redraw();
+            state.take_calls()?
+        };
+        fire(calls)
+    }
+
+    // Row major as the map, `rotation + 1` where a cell is pinned.
+    #[wasm_bindgen(getter)]
+    pub fn pins(&self) -> Vec<u8> {
+        self.state.borrow().context.pins.clone()
+    }
+
+    // What clicking a cell does: a rotation from 0 to 3 pins it, "erase"
+    // unpins it and null, the default, leaves clicks alone.
+    #[wasm_bindgen(getter)]
+    pub fn brush(&self) -> JsValue {
+        self.state.borrow().brush.to_js()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_brush(&mut self, brush: JsValue) -> Result<(), JsValue> {
+        self.state.borrow_mut().brush = Brush::from_js(&brush)?;
+        Ok(())
+    }
+
+    // How long newly collapsed cells take to come in, in milliseconds, 0
+    // for right away.
+    #[wasm_bindgen(getter)]
+    pub fn transition_duration(&self) -> f64 {
+        self.state.borrow().transition.duration
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_transition_duration(&mut self, duration: f64) -> Result<(), JsValue> {
+        let mut state = self.state.borrow_mut();
+        state.transition.set_duration(duration)?;
+        let transition = state.transition;
+        state.renderer.set_transition(transition);
+        Ok(())
+    }
+
+    // `fade` from grey or `scale` up from the middle of the cell.
+    #[wasm_bindgen(getter)]
+    pub fn transition(&self) -> String {
+        self.state.borrow().transition.style.name().to_string()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_transition(&mut self, style: &str) -> Result<(), JsValue> {
+        let mut state = self.state.borrow_mut();
+        state.transition.style = TransitionStyle::from_name(style)?;
+        let transition = state.transition;
+        state.renderer.set_transition(transition);
+        Ok(())
+    }
+
+    // Samples tiles at the nearest texel at any zoom rather than smoothing
+    // them, for pixel art tilesets.
+    #[wasm_bindgen(getter)]
+    pub fn pixel_art(&self) -> bool {
+        self.state.borrow().pixel_art
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_pixel_art(&mut self, enabled: bool) {
+        let mut state = self.state.borrow_mut();
+        state.pixel_art = enabled;
+        state.renderer.set_pixel_art(enabled);
+        state.redraw();
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_onprogress(&mut self, handler: Option<Function>) {
+        self.state.borrow_mut().handlers.onprogress = handler;
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_oncomplete(&mut self, handler: Option<Function>) {
+        self.state.borrow_mut().handlers.oncomplete = handler;
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_oncontradiction(&mut self, handler: Option<Function>) {
+        self.state.borrow_mut().handlers.oncontradiction = handler;
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_onbacktrack(&mut self, handler: Option<Function>) {
+        self.state.borrow_mut().handlers.onbacktrack = handler;
+    }
+
+    // Stops the animation loop and unhooks every listener. The canvas is left
+    // as it is; the instance does nothing afterwards.
+    pub fn destroy(&mut self) {
+        self.pause();
+        // The frame closure holds a reference to its own slot, dropping it
+        // here is what breaks that cycle.
+        self.frame_callback.borrow_mut().take();
+        if let Some((observer, _)) = self.resize_callback.take() {
+            observer.disconnect();
+        }
+        // Also run on drop, where there is nobody to report to. Removing a
+        // listener only fails for a page torn down under us, which is fine.
+        if let Canvas::Html(canvas) = &self.state.borrow().canvas {
+            for (kind, closure) in self.view_callbacks.drain(..) {
+                let _ = canvas
+                    .remove_event_listener_with_callback(kind, closure.as_ref().unchecked_ref());
+            }
+        }
+        if let Some(image) = self.image.take() {
+            image.set_onload(None);
+        }
+        self.load_callback.take();
+        let closure = self.worker_callback.take();
+        if let (Some(closure), Some(worker)) = (closure, self.state.borrow().worker.as_ref()) {
+            let _ = worker
+                .remove_event_listener_with_callback("message", closure.as_ref().unchecked_ref());
+        }
+    }
+
+    // Wheel zooms around the pointer, dragging pans and a double click goes
+    // back to the whole map. A click paints with the brush.
+    fn add_view_controls(&mut self, canvas: &HtmlCanvasElement) -> Result<(), JsValue> {
+        let handlers: [(&'static str, MouseHandler); 6] = [
+            ("wheel", |state, event, pixel| {
+                if let Some(event) = event.dyn_ref::<WheelEvent>() {
+                    // Lines rather than pixels in some browsers.
+                    let delta = match event.delta_mode() {
+                        WheelEvent::DOM_DELTA_PIXEL => event.delta_y(),
+                        _ => event.delta_y() * 16.,
+                    };
+                    let mut view = state.view;
+                    let point = to_square((state.canvas.width(), state.canvas.height()), pixel);
+                    view.zoom_at((-delta * 0.002).exp(), point);
+                    // Off the map or zoomed all the way, the page scrolls.
+                    if view != state.view && state.cell_at(pixel).is_some() {
+                        event.prevent_default();
+                        state.set_view(view);
+                    }
+                }
+            }),
+            ("mousedown", |state, event, pixel| {
+                if event.button() == 0 {
+                    state.drag = Some(pixel);
+                    state.press = Some(pixel);
+                }
+            }),
+            ("mousemove", |state, _, pixel| {
+                if let Some(last) = state.drag.replace(pixel) {
+                    let min_size = state.canvas.width().min(state.canvas.height()) as f64;
+                    let mut view = state.view;
+                    view.pan(((pixel.0 - last.0) / min_size, (pixel.1 - last.1) / min_size));
+                    state.set_view(view);
+                }
+                let hovered = state.cell_at(pixel);
+                state.set_highlight(Highlight::Hovered, hovered);
+            }),
+            ("mouseup", |state, _, pixel| {
+                state.drag = None;
+                let press = state.press.take();
+                let clicked = press.is_some_and(|press| {
+                    (pixel.0 - press.0).hypot(pixel.1 - press.1) < CLICK_DISTANCE
+                });
+                if let (true, Some((x, y))) = (clicked, state.cell_at(pixel)) {
+                    match state.brush {
+                        Brush::Off => {}
+                        Brush::Pin(rotation) => state.pin(x, y, Some(rotation)),
+                        Brush::Erase => state.pin(x, y, None),
+                    }
+                }
+            }),
+            ("mouseleave", |state, _, _| {
+                state.drag = None;
+                state.press = None;
+                state.set_highlight(Highlight::Hovered, None);
+            }),
+            ("dblclick", |state, _, _| state.set_view(View::default())),
+        ];
+        for (kind, handler) in handlers {
+            let state = self.state.clone();
+            let target = canvas.clone();
+            let closure = Closure::<dyn FnMut(MouseEvent)>::new(move |event: MouseEvent| {
+                // Events come in CSS pixels, the canvas may have more or fewer.
+                let pixel = (
+                    event.offset_x() as f64 * target.width() as f64
+                        / target.client_width().max(1) as f64,
+                    event.offset_y() as f64 * target.height() as f64
+                        / target.client_height().max(1) as f64,
+                );
+                handler(&mut state.borrow_mut(), &event, pixel);
+            });
+            canvas.add_event_listener_with_callback(kind, closure.as_ref().unchecked_ref())?;
+            self.view_callbacks.push((kind, closure));
+        }
+        Ok(())
+    }
+
+    // Keeps the canvas one canvas pixel per device pixel as its size on the
+    // page changes.
+    fn fit_canvas(&mut self, canvas: &HtmlCanvasElement) -> Result<(), JsValue> {
+        let state = self.state.clone();
+        let target = canvas.clone();
+        let closure = Closure::<dyn FnMut(Array)>::new(move |entries: Array| {
+            if resize_canvas(&target, device_pixel_size(&entries.get(0), &target)) {
+                // Resizing clears the canvas, running it is redrawn next frame.
+                state.borrow_mut().redraw();
+            }
+        });
+        let observer = ResizeObserver::new(closure.as_ref().unchecked_ref())?;
+        observer.observe(canvas);
+        self.resize_callback = Some((observer, closure));
+        Ok(())
+    }
+
+    fn request_frame(callback: &FrameCallback) -> i32 {
+        request_animation_frame(callback.borrow().as_ref().unwrap().as_ref().unchecked_ref())
+    }
+}
+
+// Gives the canvas `size` pixels, returning whether that changed anything. A
+// canvas the page leaves unsized is laid out at its pixel size, that one keeps
+// the size it had on the page rather than growing with its pixels.
+fn resize_canvas(canvas: &HtmlCanvasElement, size: (u32, u32)) -> bool {
+    if size == (canvas.width(), canvas.height()) || size.0 == 0 || size.1 == 0 {
+        return false;
+    }
+    let laid_out = (canvas.client_width(), canvas.client_height());
+    canvas.set_width(size.0);
+    canvas.set_height(size.1);
+    if (canvas.client_width(), canvas.client_height()) != laid_out {
+        let style = canvas.style();
+        let _ = style.set_property("width", &format!("{}px", laid_out.0));
+        let _ = style.set_property("height", &format!("{}px", laid_out.1));
+    }
+    true
+}
+
+// The content box of a `ResizeObserverEntry` in device pixels, exactly where
+// the browser reports them and from the CSS size otherwise, as for an
+// undefined `entry`.
+fn device_pixel_size(entry: &JsValue, canvas: &HtmlCanvasElement) -> (u32, u32) {
+    let size = Reflect::get(entry, &JsValue::from_str("devicePixelContentBoxSize"))
+        .map(|sizes| Reflect::get_u32(&sizes, 0).unwrap_or(JsValue::UNDEFINED))
+        .unwrap_or(JsValue::UNDEFINED);
+    let get = |key: &str| {
+        Reflect::get(&size, &JsValue::from_str(key))
+            .ok()
+            .and_then(|value| value.as_f64())
+    };
+    if let (Some(width), Some(height)) = (get("inlineSize"), get("blockSize")) {
+        return (width as u32, height as u32);
+    }
+    let ratio = window().map_or(1., |window| window.device_pixel_ratio());
+    (
+        (canvas.client_width() as f64 * ratio).round() as u32,
+        (canvas.client_height() as f64 * ratio).round() as u32,
+    )
+}
+
+impl Drop for WfcApp {
+    fn drop(&mut self) {
+        self.destroy();
+    }
+}
+
+impl AppState {
+    fn take_calls(&mut self) -> Result<Vec<(Function, JsValue)>, JsValue> {
+        let events = std::mem::take(&mut self.context.events);
+        self.handlers.prepare(events, &self.context)
+    }
+
+    // Whether the renderer draws from the options of undecided cells, which
+    // then have to come over from the worker.
+    fn shows_options(&self) -> bool {
+        self.display_mode != DisplayMode::Plain || self.entropy_overlay
+    }
+
+    fn post_display(&self) -> Result<(), JsValue> {
+        if let (Some(worker), true) = (self.worker.as_ref(), self.worker_started) {
+            worker::post_display(worker, self.shows_options())?;
+        }
+        Ok(())
+    }
+
+    fn start_worker(&mut self) -> Result<(), JsValue> {
+        if let Some(worker) = self.worker.as_ref() {
+            if self.worker_ready && !self.worker_started && self.context.tileset.is_some() {
+                self.worker_started = true;
+                worker::post_start(worker, &self.context, self.shows_options())?;
+                if self.paused {
+                    worker::post_command(worker, "pause", None)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Messages only go out once the worker got its `start`, before that it
+    // has nothing to apply them to.
+    fn post_to_worker(&self, kind: &str, seed: Option<u32>) {
+        if let Some(worker) = self.worker.as_ref() {
+            if self.worker_started {
+                if let Err(error) = worker::post_command(worker, kind, seed) {
+                    console::error_1(&error);
+                }
+            }
+        }
+    }
+
+    fn draw_frame(&mut self) -> Result<(), Error> {
+        let mut changed = vec![];
+        if self.worker.is_none() {
+            let date = Date::new_0();
+            for _ in 0..self.steps_per_frame {
+                match self.context.update(date.get_time() - self.start_time) {
+                    Some(position) => changed.push(position),
+                    None => break,
+                }
+            }
+        }
+        self.render(&changed)
+    }
+
+    fn render(&mut self, changed: &[usize]) -> Result<(), Error> {
+        let context = &self.context;
+        let conflict = context
+            .last_conflict
+            .map(|(x, y)| y * context.map_width + x);
+        self.highlights
+            .set(Highlight::LastCollapsed, context.last_collapsed);
+        self.highlights.set(Highlight::Contradiction, conflict);
+        self.renderer.set_highlights(&self.highlights);
+        // With a worker nothing is stepped here, only streamed in.
+        let streamed = std::mem::take(&mut self.streamed);
+        let changed = if streamed.is_empty() {
+            changed
+        } else {
+            &streamed[..]
+        };
+        render(
+            &mut *self.renderer,
+            &mut self.context,
+            changed,
+            (self.canvas.width(), self.canvas.height()),
+            Date::now() - self.clock_start,
+        )
+    }
+
+    fn set_view(&mut self, view: View) {
+        self.view = view;
+        self.renderer.set_view(view);
+        self.redraw();
+    }
+
+    // Draws right away when paused, otherwise the next frame shows it.
+    fn redraw(&mut self) {
+        if self.paused {
+            if let Err(error) = self.render(&[]) {
+                console::error_1(&error.into());
+            }
+        }
+    }
+
+    fn set_highlight(&mut self, highlight: Highlight, cell: Option<(usize, usize)>) {
+        let cell = cell.map(|(x, y)| y * self.context.map_width + x);
+        if self.highlights.get(highlight) != cell {
+            self.highlights.set(highlight, cell);
+            self.redraw();
+        }
+    }
+
+    // The worker, when there is one, does the solving, the copy here only
+    // keeps the pins to start it with.
+    fn pin(&mut self, x: usize, y: usize, rotation: Option<usize>) {
+        match self.worker.as_ref() {
+            Some(worker) => {
+                self.context.pins[y * self.context.map_width + x] =
+                    rotation.map_or(0, |rotation| rotation as u8 + 1);
+                if self.worker_started {
+                    if let Err(error) = worker::post_pin(worker, x, y, rotation) {
+                        console::error_1(&error);
+                    }
+                }
+            }
+            None => self.context.pin(x, y, rotation),
+        }
+        self.redraw();
+    }
+
+    // The map cell under a point of the canvas, in canvas pixels.
+    fn cell_at(&self, pixel: (f64, f64)) -> Option<(usize, usize)> {
+        let point = to_square((self.canvas.width(), self.canvas.height()), pixel);
+        self.view
+            .cell_at(point, self.context.map_width, self.context.map_height)
+    }
+
+    fn set_tileset(&mut self, tileset: Tileset) -> Result<(), Error> {
+        self.renderer.set_tileset(&tileset)?;
+        self.context.set_tileset(tileset);
+        self.context.map_dirty = true;
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use crate::error::Error;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlCanvasElement, OffscreenCanvas, WebGl2RenderingContext};
+
+// Either kind of canvas the renderer can draw into. An `OffscreenCanvas` works
+// inside a worker and does not need to be attached to any document.
+#[derive(Clone)]
+pub enum Canvas {
+    Html(HtmlCanvasElement),
+    Offscreen(OffscreenCanvas),
+}
+
+impl Canvas {
+    pub fn from_js(value: JsValue) -> Result<Canvas, Error> {
+        if value.is_instance_of::<HtmlCanvasElement>() {
+            Ok(Canvas::Html(value.unchecked_into()))
+        } else if value.is_instance_of::<OffscreenCanvas>() {
+            Ok(Canvas::Offscreen(value.unchecked_into()))
+        } else {
+            Err(Error::MissingCanvas(
+                "expected a canvas element or an OffscreenCanvas".to_string(),
+            ))
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        match self {
+            Canvas::Html(canvas) => canvas.width(),
+            Canvas::Offscreen(canvas) => canvas.width(),
+        }
+    }
+
+    pub fn height(&self) -> u32 {
+        match self {
+            Canvas::Html(canvas) => canvas.height(),
+            Canvas::Offscreen(canvas) => canvas.height(),
+        }
+    }
+
+    // Fails with `NoWebGl2` when the browser lacks it, or when the canvas
+    // already has a context of another kind.
+    pub fn get_webgl2_context(&self) -> Result<WebGl2RenderingContext, Error> {
+        let context = match self {
+            Canvas::Html(canvas) => canvas.get_context("webgl2"),
+            Canvas::Offscreen(canvas) => canvas.get_context("webgl2"),
+        };
+        context
+            .ok()
+            .flatten()
+            .ok_or(Error::NoWebGl2)?
+            .dyn_into::<WebGl2RenderingContext>()
+            .map_err(|_| Error::NoWebGl2)
+    }
+}
@@ -0,0 +1,432 @@
+use crate::error::Error;
+use crate::highlight::{Highlights, GRID_MIN_CELL, OUTLINE_WIDTH};
+use crate::raster::{domain_color, entropy_color, BACKGROUND, ENTROPY_ALPHA, RULED_OUT};
+use crate::renderer::{DisplayMode, Renderer};
+use crate::tileset::Tileset;
+use crate::transition::{CollapseTimes, Transition, TransitionStyle};
+use crate::update::Context;
+use crate::view::View;
+use std::f64::consts::FRAC_PI_2;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::{Clamped, JsCast};
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, ImageData};
+
+// Draws the map with `drawImage` for browsers without WebGL2. The output
+// matches the shader: a square letterboxed map, undecided cells in light
+// grey, and each tile turned to its rotation.
+pub struct Canvas2dRenderer {
+    context: CanvasRenderingContext2d,
+    // The tile image, as something `drawImage` accepts.
+    tile: Option<HtmlCanvasElement>,
+    map: Vec<u8>,
+    map_width: usize,
+    map_height: usize,
+    // Option masks as last drawn, outside of `Plain` mode or with the overlay.
+    masks: Vec<u8>,
+    display_mode: DisplayMode,
+    entropy_overlay: bool,
+    view: View,
+    pixel_art: bool,
+    highlights: Highlights,
+    transition: Transition,
+    collapse_times: CollapseTimes,
+    // Cells still coming in, drawn again on every `draw` until they are.
+    animating: Vec<usize>,
+    now: f64,
+    // Size of the tile image, for `View::crisp`.
+    tile_width: usize,
+    // Cells to draw on the next `draw`, None for the whole canvas.
+    pending: Option<Vec<usize>>,
+    window_size: (u32, u32),
+}
+
+impl Canvas2dRenderer {
+    pub fn new(canvas: &HtmlCanvasElement) -> Result<Self, Error> {
+        let context = canvas
+            .get_context("2d")
+            .ok()
+            .flatten()
+            .and_then(|context| context.dyn_into::<CanvasRenderingContext2d>().ok())
+            .ok_or_else(|| Error::MissingCanvas("the canvas has no 2d context".to_string()))?;
+        Ok(Canvas2dRenderer {
+            context,
+            tile: None,
+            map: vec![],
+            map_width: 0,
+            map_height: 0,
+            masks: vec![],
+            display_mode: DisplayMode::Plain,
+            entropy_overlay: false,
+            view: View::default(),
+            pixel_art: false,
+            highlights: Highlights::default(),
+            transition: Transition::default(),
+            collapse_times: CollapseTimes::default(),
+            animating: vec![],
+            now: 0.,
+            tile_width: 0,
+            pending: None,
+            window_size: (0, 0),
+        })
+    }
+
+    fn draw_cell(&self, position: usize) -> Result<(), Error> {
+        let (window_width, window_height) = (self.window_size.0 as f64, self.window_size.1 as f64);
+        let min_size = window_width.min(window_height);
+        let origin = (
+            ((window_width - min_size) / 2.).floor(),
+            ((window_height - min_size) / 2.).floor(),
+        );
+        let (x, y) = (position % self.map_width, position / self.map_width);
+        // Whole pixel edges, so that neighbouring cells meet without seams.
+        let scale = min_size * self.view.zoom;
+        let edge = |i: usize, count: usize, offset: f64| {
+            ((i as f64 / count as f64 - offset) * scale).floor()
+        };
+        let left = origin.0 + edge(x, self.map_width, self.view.offset.0);
+        let top = origin.1 + edge(y, self.map_height, self.view.offset.1);
+        let width = origin.0 + edge(x + 1, self.map_width, self.view.offset.0) - left;
+        let height = origin.1 + edge(y + 1, self.map_height, self.view.offset.1) - top;
+        if left >= window_width || top >= window_height || left + width <= 0. || top + height <= 0.
+        {
+            return Ok(());
+        }
+
+        self.draw_content(position, left, top, width, height)?;
+        if let Some(color) = self.highlights.outline(position) {
+            let line = OUTLINE_WIDTH.min(width / 2.).min(height / 2.);
+            self.fill(color, left, top, width, line);
+            self.fill(color, left, top + height - line, width, line);
+            self.fill(color, left, top + line, line, height - 2. * line);
+            self.fill(
+                color,
+                left + width - line,
+                top + line,
+                line,
+                height - 2. * line,
+            );
+        } else if self.highlights.grid && width.min(height) >= GRID_MIN_CELL {
+            let color = self.highlights.grid_color;
+            self.fill(color, left + width - 1., top, 1., height);
+            self.fill(color, left, top + height - 1., width - 1., 1.);
+        }
+        Ok(())
+    }
+
+    fn draw_content(
+        &self,
+        position: usize,
+        left: f64,
+        top: f64,
+        width: f64,
+        height: f64,
+    ) -> Result<(), Error> {
+        let map_entry = self.map[position];
+        if map_entry != 0 {
+            let rotation = (map_entry - 1) % 4;
+            let since = self.now - self.collapse_times.get(position);
+            let progress = self.transition.progress(since);
+            if progress >= 1. {
+                return self.draw_tile(rotation, left, top, width, height, 1.);
+            }
+            self.fill(BACKGROUND, left, top, width, height);
+            return match self.transition.style {
+                TransitionStyle::Fade => {
+                    self.draw_tile(rotation, left, top, width, height, progress)
+                }
+                TransitionStyle::Scale if progress > 0. => self.draw_tile(
+                    rotation,
+                    left + width * (1. - progress) / 2.,
+                    top + height * (1. - progress) / 2.,
+                    width * progress,
+                    height * progress,
+                    1.,
+                ),
+                TransitionStyle::Scale => Ok(()),
+            };
+        }
+        let mask = self.masks.get(position).copied().unwrap_or(0);
+        match self.display_mode {
+            DisplayMode::Plain => self.fill(BACKGROUND, left, top, width, height),
+            DisplayMode::Superposition => {
+                // The rotations in a 2x2 grid, faded over the background.
+                let (half_width, half_height) = ((width / 2.).floor(), (height / 2.).floor());
+                for rotation in 0..4 {
+                    let quadrant_left = left + if rotation % 2 == 1 { half_width } else { 0. };
+                    let quadrant_top = top + if rotation / 2 == 1 { half_height } else { 0. };
+                    let quadrant_width = if rotation % 2 == 1 {
+                        width - half_width
+                    } else {
+                        half_width
+                    };
+                    let quadrant_height = if rotation / 2 == 1 {
+                        height - half_height
+                    } else {
+                        half_height
+                    };
+                    if (mask >> rotation) & 1 == 0 {
+                        self.fill(
+                            RULED_OUT,
+                            quadrant_left,
+                            quadrant_top,
+                            quadrant_width,
+                            quadrant_height,
+                        );
+                        continue;
+                    }
+                    self.fill(
+                        BACKGROUND,
+                        quadrant_left,
+                        quadrant_top,
+                        quadrant_width,
+                        quadrant_height,
+                    );
+                    self.draw_tile(
+                        rotation,
+                        quadrant_left,
+                        quadrant_top,
+                        quadrant_width,
+                        quadrant_height,
+                        0.6,
+                    )?;
+                }
+            }
+            DisplayMode::DomainSize => {
+                self.fill(domain_color(mask.count_ones()), left, top, width, height)
+            }
+        }
+        if self.entropy_overlay {
+            let count = mask.count_ones();
+            // Contradictions are painted solid, as in the shader.
+            let alpha = if count == 0 { 1. } else { ENTROPY_ALPHA };
+            self.context.set_global_alpha(alpha);
+            self.fill(entropy_color(count), left, top, width, height);
+            self.context.set_global_alpha(1.);
+        }
+        Ok(())
+    }
+
+    fn fill(&self, color: [u8; 4], left: f64, top: f64, width: f64, height: f64) {
+        self.context.set_fill_style(&JsValue::from_str(&format!(
+            "rgba({}, {}, {}, {})",
+            color[0],
+            color[1],
+            color[2],
+            color[3] as f64 / 255.
+        )));
+        self.context.fill_rect(left, top, width, height);
+    }
+
+    fn draw_tile(
+        &self,
+        rotation: u8,
+        left: f64,
+        top: f64,
+        width: f64,
+        height: f64,
+        alpha: f64,
+    ) -> Result<(), Error> {
+        // The shader samples the tile at (1 - v, u) for rotation 1, which
+        // is the tile turned a quarter counterclockwise.
+        let angle = -(rotation as f64) * FRAC_PI_2;
+        let (tile_width, tile_height) = match rotation {
+            1 | 3 => (height, width),
+            _ => (width, height),
+        };
+        self.context
+            .set_transform(
+                angle.cos(),
+                angle.sin(),
+                -angle.sin(),
+                angle.cos(),
+                left + width / 2.,
+                top + height / 2.,
+            )
+            .map_err(Error::render)?;
+        // Clear first, transparent tile pixels would let the old cell show.
+        if alpha == 1. {
+            self.context
+                .clear_rect(-tile_width / 2., -tile_height / 2., tile_width, tile_height);
+        }
+        self.context.set_global_alpha(alpha);
+        let drawn = self
+            .context
+            .draw_image_with_html_canvas_element_and_dw_and_dh(
+                self.tile.as_ref().unwrap(),
+                -tile_width / 2.,
+                -tile_height / 2.,
+                tile_width,
+                tile_height,
+            );
+        self.context.set_global_alpha(1.);
+        self.context
+            .set_transform(1., 0., 0., 1., 0., 0.)
+            .map_err(Error::render)?;
+        drawn.map_err(Error::render)
+    }
+}
+
+impl Renderer for Canvas2dRenderer {
+    fn set_tileset(&mut self, tileset: &Tileset) -> Result<(), Error> {
+        let tile = self
+            .context
+            .canvas()
+            .and_then(|canvas| canvas.owner_document())
+            .and_then(|document| document.create_element("canvas").ok())
+            .and_then(|element| element.dyn_into::<HtmlCanvasElement>().ok())
+            .ok_or_else(|| Error::MissingCanvas("could not create a canvas".to_string()))?;
+        tile.set_width(tileset.width as u32);
+        tile.set_height(tileset.height as u32);
+        let image = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&tileset.data[..]),
+            tileset.width as u32,
+            tileset.height as u32,
+        )
+        .map_err(|_| Error::BadTileset("could not copy the tile image".to_string()))?;
+        tile.get_context("2d")
+            .ok()
+            .flatten()
+            .and_then(|context| context.dyn_into::<CanvasRenderingContext2d>().ok())
+            .ok_or_else(|| Error::MissingCanvas("could not create a canvas".to_string()))?
+            .put_image_data(&image, 0., 0.)
+            .map_err(|_| Error::BadTileset("could not copy the tile image".to_string()))?;
+        self.tile = Some(tile);
+        self.tile_width = tileset.width;
+        self.pending = None;
+        Ok(())
+    }
+
+    fn update_cells(&mut self, context: &Context, cells: Option<&[usize]>) -> Result<(), Error> {
+        match (cells, self.pending.as_mut()) {
+            (Some(cells), Some(pending)) if self.map.len() == context.map.len() => {
+                for &position in cells {
+                    self.map[position] = context.map[position];
+                    pending.push(position);
+                }
+            }
+            _ => {
+                self.map = context.map.clone();
+                self.map_width = context.map_width;
+                self.map_height = context.map_height;
+                self.pending = None;
+            }
+        }
+        let collapsed = self.collapse_times.update(&context.map, cells, self.now);
+        match collapsed {
+            Some(collapsed) if self.transition.duration > 0. => self.animating.extend(collapsed),
+            Some(_) => {}
+            None => self.animating.clear(),
+        }
+        if self.display_mode != DisplayMode::Plain || self.entropy_overlay {
+            let masks = context.option_masks();
+            let (map, old_masks) = (&self.map, &self.masks);
+            if let Some(pending) = self.pending.as_mut() {
+                pending.extend((0..masks.len()).filter(|&position| {
+                    map[position] == 0 && old_masks.get(position) != Some(&masks[position])
+                }));
+            }
+            self.masks = masks;
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self) -> Result<(), Error> {
+        if self.tile.is_none() || self.map.is_empty() {
+            return Ok(());
+        }
+        match self.pending.replace(vec![]) {
+            Some(cells) => {
+                for position in cells {
+                    self.draw_cell(position)?;
+                }
+            }
+            None => {
+                let (width, height) = self.window_size;
+                let min_size = width.min(height) as f64;
+                let crisp =
+                    self.pixel_art || self.view.crisp(min_size, self.map_width, self.tile_width);
+                self.context.set_image_smoothing_enabled(!crisp);
+                self.context.clear_rect(0., 0., width as f64, height as f64);
+                for position in 0..self.map.len() {
+                    self.draw_cell(position)?;
+                }
+            }
+        }
+        let animating = std::mem::take(&mut self.animating);
+        for &position in &animating {
+            self.draw_cell(position)?;
+        }
+        self.animating = animating
+            .into_iter()
+            .filter(|&position| {
+                let since = self.now - self.collapse_times.get(position);
+                self.transition.progress(since) < 1.
+            })
+            .collect();
+        Ok(())
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        if self.window_size != (width, height) {
+            self.window_size = (width, height);
+            self.pending = None;
+        }
+    }
+
+    fn set_display_mode(&mut self, mode: DisplayMode) {
+        if self.display_mode != mode {
+            self.display_mode = mode;
+            self.pending = None;
+        }
+    }
+
+    fn set_entropy_overlay(&mut self, enabled: bool) {
+        if self.entropy_overlay != enabled {
+            self.entropy_overlay = enabled;
+            self.pending = None;
+        }
+    }
+
+    fn set_view(&mut self, view: View) {
+        if self.view != view {
+            self.view = view;
+            self.pending = None;
+        }
+    }
+
+    fn set_pixel_art(&mut self, enabled: bool) {
+        if self.pixel_art != enabled {
+            self.pixel_art = enabled;
+            self.pending = None;
+        }
+    }
+
+    fn set_transition(&mut self, transition: Transition) {
+        self.transition = transition;
+    }
+
+    fn set_clock(&mut self, now: f64) {
+        self.now = now;
+    }
+
+    fn set_highlights(&mut self, highlights: &Highlights) {
+        let old = std::mem::replace(&mut self.highlights, *highlights);
+        let restyled = Highlights {
+            cells: old.cells,
+            ..*highlights
+        } != old;
+        let cell_count = self.map.len();
+        match self.pending.as_mut() {
+            Some(pending) if !restyled => {
+                // Redrawing a cell drops its old outline.
+                for (old_cell, cell) in old.cells.iter().zip(&highlights.cells) {
+                    if old_cell != cell {
+                        let cells = old_cell.iter().chain(cell);
+                        pending.extend(cells.filter(|&&position| position < cell_count));
+                    }
+                }
+            }
+            _ => self.pending = None,
+        }
+    }
+}
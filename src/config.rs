@@ -0,0 +1,247 @@
+use crate::canvas::Canvas;
+use crate::error::Error;
+use crate::highlight::Highlights;
+use crate::renderer::{DisplayMode, RendererKind};
+use crate::transition::{Transition, TransitionStyle};
+use js_sys::{Function, Reflect};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{ImageData, Worker};
+
+pub struct Config {
+    pub width: usize,
+    pub height: usize,
+    pub seed: u32,
+    pub max_backtracks: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            width: 60,
+            height: 60,
+            seed: 0,
+            max_backtracks: 1000,
+        }
+    }
+}
+
+impl Config {
+    // Reads the known keys from a plain JS object, leaving anything missing
+    // (or an undefined `value` altogether) at its default.
+    pub fn from_js(value: &JsValue) -> Result<Self, JsValue> {
+        let mut config = Config::default();
+        if value.is_undefined() || value.is_null() {
+            return Ok(config);
+        }
+        if let Some(width) = get_number(value, "width")? {
+            config.width = width as usize;
+        }
+        if let Some(height) = get_number(value, "height")? {
+            config.height = height as usize;
+        }
+        if let Some(seed) = get_number(value, "seed")? {
+            config.seed = seed as u32;
+        }
+        if let Some(max_backtracks) = get_number(value, "max_backtracks")? {
+            config.max_backtracks = max_backtracks as usize;
+        }
+        if config.width == 0 || config.height == 0 {
+            return Err(Error::invalid("width and height must be positive").into());
+        }
+        Ok(config)
+    }
+}
+
+pub fn get_number(object: &JsValue, key: &str) -> Result<Option<f64>, JsValue> {
+    let value = Reflect::get(object, &JsValue::from_str(key))?;
+    if value.is_undefined() || value.is_null() {
+        return Ok(None);
+    }
+    value
+        .as_f64()
+        .map(Some)
+        .ok_or_else(|| Error::invalid(format!("`{}` must be a number", key)).into())
+}
+
+pub fn get_function(object: &JsValue, key: &str) -> Result<Option<Function>, JsValue> {
+    let value = Reflect::get(object, &JsValue::from_str(key))?;
+    if value.is_undefined() || value.is_null() {
+        return Ok(None);
+    }
+    value
+        .dyn_into::<Function>()
+        .map(Some)
+        .map_err(|_| Error::invalid(format!("`{}` must be a function", key)).into())
+}
+
+pub enum CanvasSource {
+    Canvas(Canvas),
+    Id(String),
+}
+
+pub enum TilesetSource {
+    Url(String),
+    Image(ImageData),
+}
+
+// What clicking a cell of the canvas does.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Brush {
+    Off,
+    // Pins the cell to this rotation.
+    Pin(usize),
+    // Leaves the cell to the solver again.
+    Erase,
+}
+
+impl Brush {
+    // A rotation from 0 to 3, "erase", or null for `Off`.
+    pub fn from_js(value: &JsValue) -> Result<Brush, Error> {
+        if value.is_undefined() || value.is_null() {
+            return Ok(Brush::Off);
+        }
+        match (value.as_f64(), value.as_string().as_deref()) {
+            (Some(rotation), _) if [0., 1., 2., 3.].contains(&rotation) => {
+                Ok(Brush::Pin(rotation as usize))
+            }
+            (_, Some("erase")) => Ok(Brush::Erase),
+            _ => Err(Error::invalid(
+                "`brush` must be a rotation from 0 to 3, \"erase\" or null",
+            )),
+        }
+    }
+
+    pub fn to_js(self) -> JsValue {
+        match self {
+            Brush::Off => JsValue::NULL,
+            Brush::Pin(rotation) => (rotation as u32).into(),
+            Brush::Erase => JsValue::from_str("erase"),
+        }
+    }
+}
+
+// Everything `start` can be told about where to draw and what to draw with,
+// on top of the solver `Config`.
+pub struct StartOptions {
+    pub canvas: CanvasSource,
+    pub tileset: TilesetSource,
+    pub config: Config,
+    pub steps_per_frame: u32,
+    // Keeps a canvas element's pixels matching its size on the page, at the
+    // device pixel ratio, as that changes, otherwise they are only matched to
+    // it at the start. `fit_window` is still read as the older name.
+    pub fit_canvas: bool,
+    pub worker: Option<Worker>,
+    pub renderer: RendererKind,
+    pub display: DisplayMode,
+    pub entropy_overlay: bool,
+    // Wheel zoom and drag to pan on a canvas element.
+    pub view_controls: bool,
+    pub pixel_art: bool,
+    // `grid` and `highlight_colors`.
+    pub highlights: Highlights,
+    // `transition` and `transition_duration` in milliseconds.
+    pub transition: Transition,
+    pub brush: Brush,
+}
+
+impl StartOptions {
+    pub fn from_js(value: &JsValue) -> Result<Self, JsValue> {
+        let mut options = StartOptions {
+            canvas: CanvasSource::Id(String::from("canvas")),
+            tileset: TilesetSource::Url(String::from("t.png")),
+            config: Config::from_js(value)?,
+            steps_per_frame: 10,
+            fit_canvas: true,
+            worker: None,
+            renderer: RendererKind::Auto,
+            display: DisplayMode::Plain,
+            entropy_overlay: false,
+            view_controls: true,
+            pixel_art: false,
+            highlights: Highlights::default(),
+            transition: Transition::default(),
+            brush: Brush::Off,
+        };
+        if value.is_undefined() || value.is_null() {
+            return Ok(options);
+        }
+        let canvas = Reflect::get(value, &JsValue::from_str("canvas"))?;
+        if let Some(id) = canvas.as_string() {
+            options.canvas = CanvasSource::Id(id);
+        } else if !canvas.is_undefined() {
+            options.canvas = CanvasSource::Canvas(Canvas::from_js(canvas).map_err(|_| {
+                Error::invalid("`canvas` must be a canvas, an OffscreenCanvas or an element id")
+            })?);
+        }
+        let tileset = Reflect::get(value, &JsValue::from_str("tileset"))?;
+        if let Some(url) = tileset.as_string() {
+            options.tileset = TilesetSource::Url(url);
+        } else if tileset.is_instance_of::<ImageData>() {
+            options.tileset = TilesetSource::Image(tileset.unchecked_into());
+        } else if !tileset.is_undefined() {
+            return Err(Error::invalid("`tileset` must be a url or an ImageData").into());
+        }
+        if let Some(steps_per_frame) = get_number(value, "steps_per_frame")? {
+            options.steps_per_frame = steps_per_frame as u32;
+        }
+        for key in ["fit_window", "fit_canvas"] {
+            if let Some(fit_canvas) = Reflect::get(value, &JsValue::from_str(key))?.as_bool() {
+                options.fit_canvas = fit_canvas;
+            }
+        }
+        let worker = Reflect::get(value, &JsValue::from_str("worker"))?;
+        if worker.is_instance_of::<Worker>() {
+            options.worker = Some(worker.unchecked_into());
+        } else if !worker.is_undefined() {
+            return Err(Error::invalid("`worker` must be a Worker").into());
+        }
+        let renderer = Reflect::get(value, &JsValue::from_str("renderer"))?;
+        options.renderer = match renderer.as_string().as_deref() {
+            Some("auto") => RendererKind::Auto,
+            Some("webgl2") => RendererKind::WebGl2,
+            Some("canvas2d") => RendererKind::Canvas2d,
+            _ if renderer.is_undefined() => RendererKind::Auto,
+            _ => {
+                return Err(Error::invalid(
+                    "`renderer` must be \"auto\", \"webgl2\" or \"canvas2d\"",
+                )
+                .into())
+            }
+        };
+        let display = Reflect::get(value, &JsValue::from_str("display"))?;
+        if let Some(display) = display.as_string() {
+            options.display = DisplayMode::from_name(&display)?;
+        }
+        let entropy_overlay = Reflect::get(value, &JsValue::from_str("entropy_overlay"))?;
+        if let Some(entropy_overlay) = entropy_overlay.as_bool() {
+            options.entropy_overlay = entropy_overlay;
+        }
+        let view_controls = Reflect::get(value, &JsValue::from_str("view_controls"))?;
+        if let Some(view_controls) = view_controls.as_bool() {
+            options.view_controls = view_controls;
+        }
+        let pixel_art = Reflect::get(value, &JsValue::from_str("pixel_art"))?;
+        if let Some(pixel_art) = pixel_art.as_bool() {
+            options.pixel_art = pixel_art;
+        }
+        let grid = Reflect::get(value, &JsValue::from_str("grid"))?;
+        if let Some(grid) = grid.as_bool() {
+            options.highlights.grid = grid;
+        }
+        let colors = Reflect::get(value, &JsValue::from_str("highlight_colors"))?;
+        if colors.is_object() {
+            options.highlights.set_colors(&colors)?;
+        }
+        let transition = Reflect::get(value, &JsValue::from_str("transition"))?;
+        if let Some(transition) = transition.as_string() {
+            options.transition.style = TransitionStyle::from_name(&transition)?;
+        }
+        if let Some(duration) = get_number(value, "transition_duration")? {
+            options.transition.set_duration(duration)?;
+        }
+        options.brush = Brush::from_js(&Reflect::get(value, &JsValue::from_str("brush"))?)?;
+        Ok(options)
+    }
+}
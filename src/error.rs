@@ -0,0 +1,79 @@
+use js_sys::Reflect;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+// Everything that can go wrong, crate-wide. Each kind reaches JS as an
+// `Error` whose `name` says which one it is.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    MissingCanvas(String),
+    NoWebGl2,
+    // The info log of the shader or program that failed.
+    ShaderCompile(String),
+    ShaderLink(String),
+    // A drawing call failed, for WebGL most likely because of a lost context.
+    Render(String),
+    BadTileset(String),
+    BadSnapshot(String),
+    Export(String),
+    // An option or argument from JS that does not make sense.
+    InvalidArgument(String),
+    Contradiction { x: usize, y: usize },
+}
+
+impl Error {
+    // For the `Result`s drawing calls return, whose errors are plain JS values.
+    pub fn render(error: JsValue) -> Error {
+        Error::Render(error.as_string().unwrap_or_else(|| format!("{:?}", error)))
+    }
+
+    pub fn invalid(message: impl Into<String>) -> Error {
+        Error::InvalidArgument(message.into())
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Error::MissingCanvas(_) => "MissingCanvasError",
+            Error::NoWebGl2 => "WebGL2UnsupportedError",
+            Error::ShaderCompile(_) => "ShaderCompileError",
+            Error::ShaderLink(_) => "ShaderLinkError",
+            Error::Render(_) => "RenderError",
+            Error::BadTileset(_) => "TilesetError",
+            Error::BadSnapshot(_) => "SnapshotError",
+            Error::Export(_) => "ExportError",
+            Error::InvalidArgument(_) => "InvalidArgumentError",
+            Error::Contradiction { .. } => "ContradictionError",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::MissingCanvas(message) => write!(f, "{}", message),
+            Error::NoWebGl2 => write!(f, "WebGL2 is not supported"),
+            Error::ShaderCompile(log) => write!(f, "shader failed to compile: {}", log),
+            Error::ShaderLink(log) => write!(f, "shader program failed to link: {}", log),
+            Error::Render(message) => write!(f, "rendering failed: {}", message),
+            Error::BadTileset(message) => write!(f, "bad tileset: {}", message),
+            Error::BadSnapshot(message) => write!(f, "bad snapshot: {}", message),
+            Error::Export(message) => write!(f, "export failed: {}", message),
+            Error::InvalidArgument(message) => write!(f, "{}", message),
+            Error::Contradiction { x, y } => write!(f, "contradiction at cell ({}, {})", x, y),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for JsValue {
+    fn from(error: Error) -> JsValue {
+        let js_error = js_sys::Error::new(&error.to_string());
+        js_error.set_name(error.name());
+        if let Error::Contradiction { x, y } = error {
+            let _ = Reflect::set(&js_error, &"x".into(), &x.into());
+            let _ = Reflect::set(&js_error, &"y".into(), &y.into());
+        }
+        js_error.into()
+    }
+}
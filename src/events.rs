@@ -0,0 +1,137 @@
+use crate::config::get_number;
+use crate::error::Error;
+use crate::update::Context;
+use js_sys::{Function, Object, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+// Things the solver reports as it goes. They are queued on the `Context` and
+// handed out to JS by whoever owns it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Event {
+    Progress { collapsed: usize, total: usize },
+    Complete,
+    Contradiction { x: usize, y: usize },
+    Backtrack { x: usize, y: usize, depth: usize },
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Progress { .. } => "progress",
+            Event::Complete => "complete",
+            Event::Contradiction { .. } => "contradiction",
+            Event::Backtrack { .. } => "backtrack",
+        }
+    }
+
+    // The object handed to JS handlers. `context` is only needed for the map
+    // that comes with `Complete`.
+    pub fn to_js(self, context: &Context) -> Result<Object, JsValue> {
+        let payload = Object::new();
+        match self {
+            Event::Progress { collapsed, total } => {
+                set(&payload, "collapsed", collapsed)?;
+                set(&payload, "total", total)?;
+            }
+            Event::Complete => {
+                Reflect::set(
+                    &payload,
+                    &JsValue::from_str("map"),
+                    &Uint8Array::from(&context.map[..]),
+                )?;
+                set(&payload, "width", context.map_width)?;
+                set(&payload, "height", context.map_height)?;
+            }
+            Event::Contradiction { x, y } => {
+                set(&payload, "x", x)?;
+                set(&payload, "y", y)?;
+            }
+            Event::Backtrack { x, y, depth } => {
+                set(&payload, "x", x)?;
+                set(&payload, "y", y)?;
+                set(&payload, "depth", depth)?;
+            }
+        }
+        Ok(payload)
+    }
+
+    // Reads back an event sent over `postMessage`, which carries its `name`
+    // under `event` next to the `to_js` fields.
+    pub fn from_js(value: &JsValue) -> Result<Event, JsValue> {
+        let name = Reflect::get(value, &JsValue::from_str("event"))?.as_string();
+        let field =
+            |key| -> Result<usize, JsValue> { Ok(get_number(value, key)?.unwrap_or(0.) as usize) };
+        match name.as_deref() {
+            Some("progress") => Ok(Event::Progress {
+                collapsed: field("collapsed")?,
+                total: field("total")?,
+            }),
+            Some("complete") => Ok(Event::Complete),
+            Some("contradiction") => Ok(Event::Contradiction {
+                x: field("x")?,
+                y: field("y")?,
+            }),
+            Some("backtrack") => Ok(Event::Backtrack {
+                x: field("x")?,
+                y: field("y")?,
+                depth: field("depth")?,
+            }),
+            _ => Err(Error::invalid("unknown event").into()),
+        }
+    }
+}
+
+// DOM style `on*` callbacks registered from JS.
+#[derive(Clone, Default)]
+pub struct EventHandlers {
+    pub onprogress: Option<Function>,
+    pub oncomplete: Option<Function>,
+    pub oncontradiction: Option<Function>,
+    pub onbacktrack: Option<Function>,
+}
+
+impl EventHandlers {
+    // Pairs each event with its handler and a payload object. Building the
+    // calls is split from making them so that the solver does not need to
+    // stay borrowed while JS runs, JS may well call back into us.
+    pub fn prepare(
+        &self,
+        events: Vec<Event>,
+        context: &Context,
+    ) -> Result<Vec<(Function, JsValue)>, JsValue> {
+        let mut calls = vec![];
+        // Only the latest progress matters, there can be one per cell.
+        let last_progress = events
+            .iter()
+            .rposition(|event| matches!(event, Event::Progress { .. }));
+        for (i, event) in events.into_iter().enumerate() {
+            let handler = match event {
+                Event::Progress { .. } if Some(i) != last_progress => continue,
+                Event::Progress { .. } => &self.onprogress,
+                Event::Complete => &self.oncomplete,
+                Event::Contradiction { .. } => &self.oncontradiction,
+                Event::Backtrack { .. } => &self.onbacktrack,
+            };
+            if let Some(handler) = handler {
+                calls.push((handler.clone(), event.to_js(context)?.into()));
+            }
+        }
+        Ok(calls)
+    }
+}
+
+pub fn fire(calls: Vec<(Function, JsValue)>) -> Result<(), JsValue> {
+    for (handler, payload) in calls {
+        handler.call1(&JsValue::NULL, &payload)?;
+    }
+    Ok(())
+}
+
+fn set(object: &Object, key: &str, value: usize) -> Result<(), JsValue> {
+    Reflect::set(
+        object,
+        &JsValue::from_str(key),
+        &JsValue::from(value as f64),
+    )?;
+    Ok(())
+}
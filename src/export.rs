@@ -0,0 +1,117 @@
+use crate::error::Error;
+use crate::raster::composite;
+use crate::update::Context;
+
+pub fn encode_png(pixels: &[u8], width: u32, height: u32) -> Result<Vec<u8>, png::EncodingError> {
+    let mut bytes = vec![];
+    {
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(pixels)?;
+    }
+    Ok(bytes)
+}
+
+// Longest side of an exported image, in pixels, the most canvases go to.
+const MAX_PNG_SIDE: usize = 16384;
+
+impl Context {
+    // The map as a PNG, `scale` pixels per cell, or the tile image's own size
+    // when `scale` is None.
+    pub fn to_png(&self, scale: Option<usize>) -> Result<Vec<u8>, Error> {
+        let tileset = self
+            .tileset
+            .as_ref()
+            .ok_or_else(|| Error::BadTileset("no tileset loaded".to_string()))?;
+        let scale = scale.unwrap_or(tileset.width).max(1);
+        let side = |cells: usize| {
+            cells
+                .checked_mul(scale)
+                .filter(|side| *side <= MAX_PNG_SIDE)
+                .ok_or_else(|| {
+                    Error::invalid(format!(
+                        "a scale of {} makes the image larger than {} pixels a side",
+                        scale, MAX_PNG_SIDE
+                    ))
+                })
+        };
+        let (width, height) = (side(self.map_width)?, side(self.map_height)?);
+        let pixels = composite(&self.map, self.map_width, self.map_height, tileset, scale);
+        encode_png(&pixels, width as u32, height as u32)
+            .map_err(|error| Error::Export(error.to_string()))
+    }
+}
+
+// What the values in the map stand for. There is a single tile, so the id is
+// only 0 for undecided cells and `rotation + 1` otherwise.
+pub const LEGEND: [(u8, &str, Option<u32>); 5] = [
+    (0, "undecided", None),
+    (1, "tile", Some(0)),
+    (2, "tile", Some(90)),
+    (3, "tile", Some(180)),
+    (4, "tile", Some(270)),
+];
+
+impl Context {
+    // The map as rows of ids, with the legend and what it was generated from,
+    // ready to be checked in next to a level.
+    pub fn to_json(&self) -> String {
+        let legend = LEGEND
+            .iter()
+            .map(|(id, name, rotation)| {
+                let rotation = rotation.map_or("null".to_string(), |rotation| rotation.to_string());
+                format!(
+                    "{{\"id\":{},\"name\":\"{}\",\"rotation\":{}}}",
+                    id, name, rotation
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let rows = self
+            .map
+            .chunks(self.map_width)
+            .map(|row| format!("[{}]", join(row, ",")))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            "{{\"seed\":{},\"config\":{{\"width\":{},\"height\":{},\"seed\":{},\"max_backtracks\":{}}},\"legend\":[{}],\"map\":[{}]}}",
+            self.seed,
+            self.map_width,
+            self.map_height,
+            self.seed,
+            self.max_backtracks,
+            legend,
+            rows
+        )
+    }
+
+    // One line per map row. The seed, config and legend go first as `#`
+    // comment lines, which most CSV readers can be told to skip.
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!(
+            "# seed={} width={} height={} max_backtracks={}\n",
+            self.seed, self.map_width, self.map_height, self.max_backtracks
+        );
+        for (id, name, rotation) in LEGEND.iter() {
+            match rotation {
+                Some(rotation) => csv += &format!("# {}={} rotation={}\n", id, name, rotation),
+                None => csv += &format!("# {}={}\n", id, name),
+            }
+        }
+        for row in self.map.chunks(self.map_width) {
+            csv += &join(row, ",");
+            csv += "\n";
+        }
+        csv
+    }
+}
+
+fn join(values: &[u8], separator: &str) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
@@ -0,0 +1,93 @@
+use crate::config::{get_function, get_number};
+use crate::error::Error;
+use crate::generator::WfcGenerator;
+use crate::update::Status;
+use js_sys::{Date, Function, Object, Promise, Reflect, Uint8Array};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::ImageData;
+
+#[wasm_bindgen]
+extern "C" {
+    // The global one rather than `Window::set_timeout...` so that this also
+    // works inside a worker.
+    #[wasm_bindgen(js_name = setTimeout)]
+    pub fn set_timeout(handler: &Function, timeout: i32) -> i32;
+}
+
+type ChunkCallback = Rc<RefCell<Option<Closure<dyn FnMut()>>>>;
+
+// Runs the solver to the end without rendering anything. The work is done in
+// slices of `time_slice` milliseconds (10 by default) with a trip through the
+// event loop in between, so big maps do not freeze the page. Resolves with
+// `{ map, width, height, seed }`, rejects with a `ContradictionError` carrying
+// the `x` and `y` of the cell that could not be filled.
+#[wasm_bindgen]
+pub fn generate(tileset: &ImageData, config: JsValue) -> Result<Promise, JsValue> {
+    let mut generator = WfcGenerator::new(tileset, config.clone())?;
+    let mut time_slice = 10.;
+    if config.is_object() {
+        time_slice = get_number(&config, "time_slice")?.unwrap_or(time_slice);
+        if !time_slice.is_finite() || time_slice <= 0. {
+            return Err(
+                Error::invalid("time_slice must be a positive number of milliseconds").into(),
+            );
+        }
+        generator.set_onprogress(get_function(&config, "onprogress")?);
+    }
+
+    let generator = Rc::new(RefCell::new(generator));
+    Ok(Promise::new(&mut |resolve: Function, reject: Function| {
+        let callback: ChunkCallback = Rc::new(RefCell::new(None));
+        let next = callback.clone();
+        let generator = generator.clone();
+        *callback.borrow_mut() = Some(Closure::<dyn FnMut()>::new(move || {
+            let mut generator = generator.borrow_mut();
+            let deadline = Date::now() + time_slice;
+            let mut status = Ok(Status::Running);
+            while let Ok(Status::Running) = status {
+                status = generator.run(Some(64));
+                if matches!(status, Ok(Status::Running)) && Date::now() >= deadline {
+                    set_timeout(next.borrow().as_ref().unwrap().as_ref().unchecked_ref(), 0);
+                    return;
+                }
+            }
+            let settled = match status {
+                Ok(Status::Done) => {
+                    result(&generator).and_then(|map| resolve.call1(&JsValue::NULL, &map))
+                }
+                Ok(_) => reject.call1(&JsValue::NULL, &contradiction_error(&generator)),
+                Err(error) => reject.call1(&JsValue::NULL, &error),
+            };
+            if let Err(error) = settled {
+                let _ = reject.call1(&JsValue::NULL, &error);
+            }
+            // Nothing left to schedule, let go of ourselves.
+            next.borrow_mut().take();
+        }));
+        set_timeout(
+            callback.borrow().as_ref().unwrap().as_ref().unchecked_ref(),
+            0,
+        );
+    }))
+}
+
+fn result(generator: &WfcGenerator) -> Result<JsValue, JsValue> {
+    let result = Object::new();
+    Reflect::set(
+        &result,
+        &"map".into(),
+        &Uint8Array::from(&generator.get_map()[..]),
+    )?;
+    Reflect::set(&result, &"width".into(), &generator.width().into())?;
+    Reflect::set(&result, &"height".into(), &generator.height().into())?;
+    Reflect::set(&result, &"seed".into(), &generator.seed().into())?;
+    Ok(result.into())
+}
+
+fn contradiction_error(generator: &WfcGenerator) -> JsValue {
+    let (x, y) = generator.contradiction().unwrap_or((0, 0));
+    Error::Contradiction { x, y }.into()
+}
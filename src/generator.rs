@@ -0,0 +1,248 @@
+use crate::canvas::Canvas;
+use crate::config::Config;
+use crate::error::Error;
+use crate::events::{fire, EventHandlers};
+use crate::raster::HeadlessRenderer;
+use crate::renderer::{create_renderer, DisplayMode, Renderer, RendererKind};
+use crate::replay::{records_from_js, records_to_js};
+use crate::tileset::Tileset;
+use crate::update::{Context, Status};
+use js_sys::{Array, Function};
+use wasm_bindgen::prelude::*;
+use web_sys::ImageData;
+
+// Drives the solver from JS without any of the canvas plumbing of `start`.
+#[wasm_bindgen]
+pub struct WfcGenerator {
+    context: Context,
+    handlers: EventHandlers,
+    display_mode: DisplayMode,
+    entropy_overlay: bool,
+    pixel_art: bool,
+}
+
+#[wasm_bindgen]
+impl WfcGenerator {
+    #[wasm_bindgen(constructor)]
+    pub fn new(tileset: &ImageData, config: JsValue) -> Result<WfcGenerator, JsValue> {
+        let config = Config::from_js(&config)?;
+        let mut context = Context::new(config.width, config.height);
+        context.set_tileset(Tileset::from_image_data(tileset)?);
+        context.max_backtracks = config.max_backtracks;
+        context.set_seed(config.seed as u64);
+        Ok(WfcGenerator {
+            context,
+            handlers: EventHandlers::default(),
+            display_mode: DisplayMode::Plain,
+            entropy_overlay: false,
+            pixel_art: false,
+        })
+    }
+
+    // Returns the index of the map cell written by this step, if any.
+    pub fn step(&mut self) -> Result<Option<u32>, JsValue> {
+        let position = self.context.step();
+        self.dispatch_events()?;
+        Ok(position.map(|position| position as u32))
+    }
+
+    // Steps until the map is done or contradicts, or `max_steps` is reached.
+    pub fn run(&mut self, max_steps: Option<u32>) -> Result<Status, JsValue> {
+        let mut steps = 0;
+        while self.context.status == Status::Running && max_steps.is_none_or(|max| steps < max) {
+            self.context.step();
+            self.dispatch_events()?;
+            steps += 1;
+        }
+        Ok(self.context.status)
+    }
+
+    pub fn reset(&mut self) {
+        self.context.reset();
+    }
+
+    pub fn set_seed(&mut self, seed: u32) {
+        self.context.set_seed(seed as u64);
+    }
+
+    // Row major, 0 for undecided cells and `rotation + 1` otherwise.
+    pub fn get_map(&self) -> Vec<u8> {
+        self.context.map.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> usize {
+        self.context.map_width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> usize {
+        self.context.map_height
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn status(&self) -> Status {
+        self.context.status
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn seed(&self) -> u32 {
+        self.context.seed as u32
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_onprogress(&mut self, handler: Option<Function>) {
+        self.handlers.onprogress = handler;
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_oncomplete(&mut self, handler: Option<Function>) {
+        self.handlers.oncomplete = handler;
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_oncontradiction(&mut self, handler: Option<Function>) {
+        self.handlers.oncontradiction = handler;
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_onbacktrack(&mut self, handler: Option<Function>) {
+        self.handlers.onbacktrack = handler;
+    }
+
+    // Encodes the map as a PNG with `scale` pixels per cell, defaulting to
+    // the size of the tile image.
+    pub fn to_png(&self, scale: Option<u32>) -> Result<Vec<u8>, JsValue> {
+        Ok(self.context.to_png(scale.map(|scale| scale as usize))?)
+    }
+
+    // The map as JSON rows of ids, with a legend and the seed and config.
+    pub fn to_json(&self) -> String {
+        self.context.to_json()
+    }
+
+    // The same as `to_json`, as CSV with the extra details in `#` comments.
+    pub fn to_csv(&self) -> String {
+        self.context.to_csv()
+    }
+
+    // The complete solver state as bytes, to be handed back to `restore`
+    // later, on this generator or another one with the same tileset.
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.context.snapshot()
+    }
+
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), JsValue> {
+        Ok(self.context.restore(snapshot)?)
+    }
+
+    // Every collapse made since the last reset, as `{x, y, rotation, entropy,
+    // propagated, backtracks}` objects.
+    pub fn decision_log(&self) -> Result<Array, JsValue> {
+        records_to_js(&self.context.decision_log)
+    }
+
+    // Starts over and makes the collapses of a `decision_log` again, one per
+    // `step`, reproducing that run.
+    pub fn replay(&mut self, log: JsValue) -> Result<(), JsValue> {
+        self.context.start_replay(records_from_js(&log)?);
+        Ok(())
+    }
+
+    // Fixes the cell at `x`, `y` to `rotation`, or leaves it to the solver
+    // again without one, keeping as much of the map as still fits. Pins hold
+    // through `reset` until `clear_pins`.
+    pub fn pin(&mut self, x: u32, y: u32, rotation: Option<u32>) -> Result<(), JsValue> {
+        let (x, y, rotation) = (x as usize, y as usize, rotation.map(|r| r as usize));
+        self.context.check_pin(x, y, rotation)?;
+        self.context.pin(x, y, rotation);
+        self.dispatch_events()
+    }
+
+    pub fn clear_pins(&mut self) -> Result<(), JsValue> {
+        self.context.clear_pins();
+        self.dispatch_events()
+    }
+
+    // Row major as `get_map`, 0 where nothing is pinned.
+    #[wasm_bindgen(getter)]
+    pub fn pins(&self) -> Vec<u8> {
+        self.context.pins.clone()
+    }
+
+    // RGBA pixels of what `render` would draw into a canvas of this size,
+    // computed on the CPU.
+    pub fn rasterize(&self, width: u32, height: u32) -> Result<Vec<u8>, JsValue> {
+        let mut renderer = HeadlessRenderer::new();
+        self.draw_once(&mut renderer, width, height)?;
+        Ok(renderer.pixels)
+    }
+
+    // Draws the map as it currently is into a canvas or an `OffscreenCanvas`,
+    // e.g. for thumbnails that never get attached to the page.
+    pub fn render(&self, canvas: JsValue) -> Result<(), JsValue> {
+        let canvas = Canvas::from_js(canvas)?;
+        let mut renderer = create_renderer(&canvas, RendererKind::Auto)?;
+        self.draw_once(&mut *renderer, canvas.width(), canvas.height())?;
+        Ok(())
+    }
+
+    // How `render` and `rasterize` draw undecided cells, as on `WfcApp`.
+    #[wasm_bindgen(getter)]
+    pub fn display_mode(&self) -> String {
+        self.display_mode.name().to_string()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_display_mode(&mut self, mode: &str) -> Result<(), JsValue> {
+        self.display_mode = DisplayMode::from_name(mode)?;
+        Ok(())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn entropy_overlay(&self) -> bool {
+        self.entropy_overlay
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_entropy_overlay(&mut self, enabled: bool) {
+        self.entropy_overlay = enabled;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn pixel_art(&self) -> bool {
+        self.pixel_art
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_pixel_art(&mut self, enabled: bool) {
+        self.pixel_art = enabled;
+    }
+
+    fn dispatch_events(&mut self) -> Result<(), JsValue> {
+        if self.context.events.is_empty() {
+            return Ok(());
+        }
+        let events = std::mem::take(&mut self.context.events);
+        fire(self.handlers.prepare(events, &self.context)?)
+    }
+}
+
+impl WfcGenerator {
+    pub fn contradiction(&self) -> Option<(usize, usize)> {
+        self.context.contradiction
+    }
+
+    // The whole map, drawn by a renderer that has not shown anything before.
+    fn draw_once(&self, renderer: &mut dyn Renderer, width: u32, height: u32) -> Result<(), Error> {
+        if let Some(tileset) = self.context.tileset.as_ref() {
+            renderer.set_tileset(tileset)?;
+        }
+        renderer.resize(width, height);
+        renderer.set_display_mode(self.display_mode);
+        renderer.set_entropy_overlay(self.entropy_overlay);
+        renderer.set_pixel_art(self.pixel_art);
+        renderer.update_cells(&self.context, None)?;
+        renderer.draw()
+    }
+}
@@ -0,0 +1,171 @@
+use crate::error::Error;
+use js_sys::Reflect;
+use wasm_bindgen::prelude::*;
+
+// Cells that get an outline, in order of precedence where they coincide.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Highlight {
+    // Where propagation last ran out of options.
+    Contradiction,
+    Selected,
+    Hovered,
+    LastCollapsed,
+}
+
+impl Highlight {
+    pub const ALL: [Highlight; 4] = [
+        Highlight::Contradiction,
+        Highlight::Selected,
+        Highlight::Hovered,
+        Highlight::LastCollapsed,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Highlight::Contradiction => "contradiction",
+            Highlight::Selected => "selected",
+            Highlight::Hovered => "hovered",
+            Highlight::LastCollapsed => "last_collapsed",
+        }
+    }
+}
+
+// Outlines are this many pixels wide, inside the cell.
+pub const OUTLINE_WIDTH: f64 = 2.;
+
+// Below this many pixels per cell the grid would be all there is to see.
+pub const GRID_MIN_CELL: f64 = 4.;
+
+// What is drawn over the cells: a one pixel grid along their right and
+// bottom edges, and outlines around the highlighted ones. Colors are RGBA,
+// blended over the cell by their alpha.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Highlights {
+    pub grid: bool,
+    pub grid_color: [u8; 4],
+    // Map indices, by `Highlight as usize`.
+    pub cells: [Option<usize>; 4],
+    pub colors: [[u8; 4]; 4],
+}
+
+impl Default for Highlights {
+    fn default() -> Self {
+        Highlights {
+            grid: false,
+            grid_color: [0, 0, 0, 64],
+            cells: [None; 4],
+            colors: [
+                [255, 0, 0, 255],
+                [0, 160, 255, 255],
+                [255, 255, 255, 200],
+                [255, 200, 0, 255],
+            ],
+        }
+    }
+}
+
+impl Highlights {
+    pub fn set(&mut self, highlight: Highlight, cell: Option<usize>) {
+        self.cells[highlight as usize] = cell;
+    }
+
+    pub fn get(&self, highlight: Highlight) -> Option<usize> {
+        self.cells[highlight as usize]
+    }
+
+    // The outline color of the cell at `index`, if it has one.
+    pub fn outline(&self, index: usize) -> Option<[u8; 4]> {
+        Highlight::ALL
+            .iter()
+            .find(|&&highlight| self.get(highlight) == Some(index))
+            .map(|&highlight| self.colors[highlight as usize])
+    }
+
+    // What to draw at `position` within the cell at `index`, from 0 to 1 on
+    // both axes, for cells `cell_pixels` wide and high on screen.
+    pub fn color_at(
+        &self,
+        index: usize,
+        position: (f64, f64),
+        cell_pixels: (f64, f64),
+    ) -> Option<[u8; 4]> {
+        if let Some(color) = self.outline(index) {
+            let edge = |p: f64, size: f64| p.min(1. - p) * size;
+            if edge(position.0, cell_pixels.0).min(edge(position.1, cell_pixels.1)) < OUTLINE_WIDTH
+            {
+                return Some(color);
+            }
+        }
+        let grid = self.grid && cell_pixels.0.min(cell_pixels.1) >= GRID_MIN_CELL;
+        if grid
+            && ((1. - position.0) * cell_pixels.0 < 1. || (1. - position.1) * cell_pixels.1 < 1.)
+        {
+            return Some(self.grid_color);
+        }
+        None
+    }
+
+    // Takes colors from `{ grid, contradiction, selected, hovered,
+    // last_collapsed }`, any of them left out stays as it was.
+    pub fn set_colors(&mut self, colors: &JsValue) -> Result<(), JsValue> {
+        if let Some(color) = get_color(colors, "grid")? {
+            self.grid_color = color;
+        }
+        for highlight in Highlight::ALL {
+            if let Some(color) = get_color(colors, highlight.name())? {
+                self.colors[highlight as usize] = color;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn get_color(object: &JsValue, key: &str) -> Result<Option<[u8; 4]>, JsValue> {
+    let value = Reflect::get(object, &JsValue::from_str(key))?;
+    if value.is_undefined() || value.is_null() {
+        return Ok(None);
+    }
+    value
+        .as_string()
+        .and_then(|color| parse_color(&color))
+        .map(Some)
+        .ok_or_else(|| {
+            Error::invalid(format!(
+                "`{}` must be a color like \"#rgb\", \"#rgba\", \"#rrggbb\" or \"#rrggbbaa\"",
+                key
+            ))
+            .into()
+        })
+}
+
+// Hex colors as in CSS, opaque unless they carry an alpha.
+pub fn parse_color(color: &str) -> Option<[u8; 4]> {
+    let digits = color.strip_prefix('#')?;
+    let digits: Vec<u8> = digits
+        .chars()
+        .map(|digit| digit.to_digit(16).map(|digit| digit as u8))
+        .collect::<Option<_>>()?;
+    let channels: Vec<u8> = match digits.len() {
+        3 | 4 => digits.iter().map(|digit| digit * 17).collect(),
+        6 | 8 => digits
+            .chunks(2)
+            .map(|pair| pair[0] * 16 + pair[1])
+            .collect(),
+        _ => return None,
+    };
+    let mut rgba = [255; 4];
+    rgba[..channels.len()].copy_from_slice(&channels);
+    Some(rgba)
+}
+
+// `color` blended over `under` by its alpha.
+pub fn blend(under: [u8; 4], color: [u8; 4]) -> [u8; 4] {
+    let alpha = color[3] as f64 / 255.;
+    let mut blended = under;
+    blended[3] = under[3].max(color[3]);
+    for channel in 0..3 {
+        blended[channel] =
+            (under[channel] as f64 * (1. - alpha) + color[channel] as f64 * alpha).round() as u8;
+    }
+    blended
+}
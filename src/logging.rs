@@ -0,0 +1,119 @@
+use crate::error::Error;
+use std::sync::atomic::{AtomicU8, Ordering};
+use wasm_bindgen::prelude::*;
+
+// Log levels, most severe first. A category logs the messages at or above
+// the level it is set to.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Category {
+    Solver,
+    Propagation,
+    Render,
+}
+
+const CATEGORIES: [Category; 3] = [Category::Solver, Category::Propagation, Category::Render];
+
+// Everything is off until JS turns it on.
+static LEVELS: [AtomicU8; 3] = [
+    AtomicU8::new(Level::Off as u8),
+    AtomicU8::new(Level::Off as u8),
+    AtomicU8::new(Level::Off as u8),
+];
+
+impl Level {
+    fn from_name(name: &str) -> Option<Level> {
+        match name {
+            "off" => Some(Level::Off),
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+}
+
+impl Category {
+    fn from_name(name: &str) -> Option<Category> {
+        match name {
+            "solver" => Some(Category::Solver),
+            "propagation" => Some(Category::Propagation),
+            "render" => Some(Category::Render),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "logging")]
+    fn name(self) -> &'static str {
+        match self {
+            Category::Solver => "solver",
+            Category::Propagation => "propagation",
+            Category::Render => "render",
+        }
+    }
+}
+
+#[cfg(feature = "logging")]
+pub fn enabled(category: Category, level: Level) -> bool {
+    LEVELS[category as usize].load(Ordering::Relaxed) >= level as u8
+}
+
+#[cfg(feature = "logging")]
+pub fn write(category: Category, level: Level, message: &str) {
+    let message = JsValue::from_str(&format!("[{}] {}", category.name(), message));
+    match level {
+        Level::Off => {}
+        Level::Error => web_sys::console::error_1(&message),
+        Level::Warn => web_sys::console::warn_1(&message),
+        Level::Info => web_sys::console::info_1(&message),
+        Level::Debug | Level::Trace => web_sys::console::debug_1(&message),
+    }
+}
+
+// Sets how much gets logged, e.g. `set_log_level("trace", "propagation")`, or
+// for every category when `category` is left out. Levels are `off`, `error`,
+// `warn`, `info`, `debug` and `trace`. Builds without the `logging` feature
+// accept this but never log anything.
+#[wasm_bindgen]
+pub fn set_log_level(level: &str, category: Option<String>) -> Result<(), JsValue> {
+    let level = level_named(level)?;
+    let categories = match category.as_deref() {
+        Some(name) => vec![category_named(name)?],
+        None => CATEGORIES.to_vec(),
+    };
+    for category in categories {
+        LEVELS[category as usize].store(level as u8, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+// Whether a message at `level` in `category` would be logged, always false
+// without the `logging` feature.
+#[wasm_bindgen]
+pub fn log_enabled(level: &str, category: &str) -> Result<bool, JsValue> {
+    let level = level_named(level)?;
+    let category = category_named(category)?;
+    Ok(cfg!(feature = "logging")
+        && level != Level::Off
+        && LEVELS[category as usize].load(Ordering::Relaxed) >= level as u8)
+}
+
+fn level_named(name: &str) -> Result<Level, Error> {
+    Level::from_name(name).ok_or_else(|| Error::invalid(format!("unknown log level `{}`", name)))
+}
+
+fn category_named(name: &str) -> Result<Category, Error> {
+    Category::from_name(name)
+        .ok_or_else(|| Error::invalid(format!("unknown log category `{}`", name)))
+}
@@ -0,0 +1,460 @@
+use crate::error::Error;
+use crate::highlight::{blend, Highlights};
+use crate::renderer::{DisplayMode, Renderer};
+use crate::tileset::Tileset;
+use crate::transition::{CollapseTimes, Transition, TransitionStyle, LONG_AGO};
+use crate::update::Context;
+use crate::view::View;
+
+// What the shader paints undecided cells with, `vec4(0.9, 0.9, 0.9, 1.)`.
+pub const BACKGROUND: [u8; 4] = [230, 230, 230, 255];
+
+// What the shader paints ruled out rotations with in superposition mode.
+pub const RULED_OUT: [u8; 4] = [204, 204, 204, 255];
+
+// How much of the entropy overlay shows over undecided cells.
+pub const ENTROPY_ALPHA: f64 = 0.55;
+
+// The shader's inputs besides the map and the tileset.
+#[derive(Clone, Copy)]
+pub struct Shading<'a> {
+    // `Context::option_masks`, only read for modes other than `Plain`.
+    pub masks: &'a [u8],
+    pub mode: DisplayMode,
+    pub entropy_overlay: bool,
+    // Only used by `rasterize_shaded`, `shade` takes map positions.
+    pub view: View,
+    // Sample the atlas at the nearest texel rather than in between.
+    pub nearest: bool,
+    pub highlights: Highlights,
+    // Size of a cell on screen, which outlines and the grid are drawn in.
+    pub cell_pixels: (f64, f64),
+    // `CollapseTimes::times`, only read when the transition takes time.
+    pub collapse_times: &'a [f32],
+    pub transition: Transition,
+    pub now: f64,
+}
+
+impl Default for Shading<'_> {
+    fn default() -> Self {
+        Shading {
+            masks: &[],
+            mode: DisplayMode::Plain,
+            entropy_overlay: false,
+            view: View::default(),
+            nearest: false,
+            highlights: Highlights::default(),
+            cell_pixels: (0., 0.),
+            collapse_times: &[],
+            transition: Transition::default(),
+            now: 0.,
+        }
+    }
+}
+
+// CPU version of the fragment shader in `get_pixel_shader`. `out_position` is
+// the shader's `outPosition`: the whole map spans [0, 1) on both axes, with y
+// going down the rows of `map`.
+pub fn shade(
+    map: &[u8],
+    map_width: usize,
+    map_height: usize,
+    tileset: &Tileset,
+    shading: &Shading,
+    out_position: (f64, f64),
+) -> [u8; 4] {
+    // The map texture uses NEAREST filtering.
+    let cell_x = ((out_position.0 * map_width as f64) as usize).min(map_width - 1);
+    let cell_y = ((out_position.1 * map_height as f64) as usize).min(map_height - 1);
+    let index = cell_y * map_width + cell_x;
+    let position = (
+        (out_position.0 * map_width as f64).rem_euclid(1.),
+        (out_position.1 * map_height as f64).rem_euclid(1.),
+    );
+    let color = cell_color(map[index], tileset, shading, index, position);
+    match shading
+        .highlights
+        .color_at(index, position, shading.cell_pixels)
+    {
+        Some(outline) => blend(color, outline),
+        None => color,
+    }
+}
+
+fn cell_color(
+    map_entry: u8,
+    tileset: &Tileset,
+    shading: &Shading,
+    index: usize,
+    position: (f64, f64),
+) -> [u8; 4] {
+    if map_entry != 0 {
+        return collapsed(tileset, shading, index, (map_entry - 1) % 4, position);
+    }
+    let mask = shading.masks.get(index).copied().unwrap_or(0);
+    let color = undecided(tileset, shading, mask, position);
+    if shading.entropy_overlay {
+        return entropy_overlay(color, mask.count_ones());
+    }
+    color
+}
+
+// A decided cell, on its way in for `shading.transition.duration` after it
+// collapsed.
+fn collapsed(
+    tileset: &Tileset,
+    shading: &Shading,
+    index: usize,
+    rotation: u8,
+    position: (f64, f64),
+) -> [u8; 4] {
+    let time = shading
+        .collapse_times
+        .get(index)
+        .copied()
+        .unwrap_or(LONG_AGO);
+    let progress = shading.transition.progress(shading.now - time as f64);
+    if progress >= 1. {
+        return tile(tileset, shading.nearest, rotation, position);
+    }
+    match shading.transition.style {
+        TransitionStyle::Fade => mix(
+            BACKGROUND,
+            tile(tileset, shading.nearest, rotation, position),
+            progress,
+        ),
+        TransitionStyle::Scale => {
+            let scaled = (
+                (position.0 - 0.5) / progress + 0.5,
+                (position.1 - 0.5) / progress + 0.5,
+            );
+            if progress <= 0. || !(0. ..1.).contains(&scaled.0) || !(0. ..1.).contains(&scaled.1) {
+                return BACKGROUND;
+            }
+            tile(tileset, shading.nearest, rotation, scaled)
+        }
+    }
+}
+
+fn mix(from: [u8; 4], to: [u8; 4], t: f64) -> [u8; 4] {
+    let mut mixed = [0; 4];
+    for (channel, value) in mixed.iter_mut().enumerate() {
+        *value = (from[channel] as f64 * (1. - t) + to[channel] as f64 * t).round() as u8;
+    }
+    mixed
+}
+
+fn tile(tileset: &Tileset, nearest: bool, rotation: u8, position: (f64, f64)) -> [u8; 4] {
+    let mut atlas_position = match rotation {
+        0 => position,
+        1 => (1. - position.1, position.0),
+        2 => (1. - position.0, 1. - position.1),
+        _ => (position.1, 1. - position.0),
+    };
+    if nearest {
+        let (width, height) = (tileset.width as f64, tileset.height as f64);
+        atlas_position = (
+            ((atlas_position.0 * width).floor() + 0.5) / width,
+            ((atlas_position.1 * height).floor() + 0.5) / height,
+        );
+    }
+    sample_linear(tileset, atlas_position)
+}
+
+fn undecided(tileset: &Tileset, shading: &Shading, mask: u8, position: (f64, f64)) -> [u8; 4] {
+    match shading.mode {
+        DisplayMode::Plain => BACKGROUND,
+        DisplayMode::Superposition => {
+            let quadrant = ((position.0 * 2.) as u8, (position.1 * 2.) as u8);
+            let rotation = quadrant.0 + quadrant.1 * 2;
+            if (mask >> rotation) & 1 == 0 {
+                return RULED_OUT;
+            }
+            let sub_position = ((position.0 * 2.).fract(), (position.1 * 2.).fract());
+            let color = tile(tileset, shading.nearest, rotation, sub_position);
+            let mut blended = [0; 4];
+            for (channel, value) in blended.iter_mut().enumerate() {
+                *value =
+                    (BACKGROUND[channel] as f64 * 0.4 + color[channel] as f64 * 0.6).round() as u8;
+            }
+            blended
+        }
+        DisplayMode::DomainSize => domain_color(mask.count_ones()),
+    }
+}
+
+// The domain size heatmap, from yellow for a single rotation left to blue
+// for all four, and black for none.
+pub fn domain_color(count: u32) -> [u8; 4] {
+    if count == 0 {
+        return [0, 0, 0, 255];
+    }
+    let t = (count.min(4) - 1) as f64 / 3.;
+    let (few, many) = ([1., 0.85, 0.3], [0.25, 0.35, 0.8]);
+    let mut color = [255; 4];
+    for channel in 0..3 {
+        color[channel] = ((few[channel] * (1. - t) + many[channel] * t) * 255.).round() as u8;
+    }
+    color
+}
+
+// The entropy overlay, from red for a cell about to be forced to blue for
+// one with all four rotations open, and magenta for a contradiction.
+pub fn entropy_color(count: u32) -> [u8; 4] {
+    if count == 0 {
+        return [230, 0, 150, 255];
+    }
+    let t = (count.min(4) as f64).log2() / 2.;
+    let (low, high) = ([0.9, 0.2, 0.1], [0.2, 0.45, 0.95]);
+    let mut color = [255; 4];
+    for channel in 0..3 {
+        color[channel] = ((low[channel] * (1. - t) + high[channel] * t) * 255.).round() as u8;
+    }
+    color
+}
+
+// `entropy_color` blended over what is under it, or on its own for a cell
+// without options so contradictions stand out.
+fn entropy_overlay(under: [u8; 4], count: u32) -> [u8; 4] {
+    let color = entropy_color(count);
+    if count == 0 {
+        return color;
+    }
+    let mut blended = [255; 4];
+    for channel in 0..3 {
+        blended[channel] = (under[channel] as f64 * (1. - ENTROPY_ALPHA)
+            + color[channel] as f64 * ENTROPY_ALPHA)
+            .round() as u8;
+    }
+    blended
+}
+
+// `texture()` on the atlas: LINEAR filtering clamped to the edges, as set up
+// in `WebGl2Renderer::set_tileset`. Only the full size level, the shader's
+// mipmaps come in when the tiles are drawn smaller than the atlas.
+fn sample_linear(tileset: &Tileset, position: (f64, f64)) -> [u8; 4] {
+    let x = position.0 * tileset.width as f64 - 0.5;
+    let y = position.1 * tileset.height as f64 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (alpha, beta) = (x - x0, y - y0);
+    let texel = |i: f64, j: f64| {
+        let i = (i.max(0.) as usize).min(tileset.width - 1);
+        let j = (j.max(0.) as usize).min(tileset.height - 1);
+        let offset = (j * tileset.width + i) * 4;
+        &tileset.data[offset..offset + 4]
+    };
+    let (top_left, top_right) = (texel(x0, y0), texel(x0 + 1., y0));
+    let (bottom_left, bottom_right) = (texel(x0, y0 + 1.), texel(x0 + 1., y0 + 1.));
+    let mut color = [0; 4];
+    for (channel, value) in color.iter_mut().enumerate() {
+        let top = top_left[channel] as f64 * (1. - alpha) + top_right[channel] as f64 * alpha;
+        let bottom =
+            bottom_left[channel] as f64 * (1. - alpha) + bottom_right[channel] as f64 * alpha;
+        *value = (top * (1. - beta) + bottom * beta).round() as u8;
+    }
+    color
+}
+
+// The image the WebGL renderer draws into a `window_width` x `window_height`
+// canvas, rows from the top, including the letterboxing around the square map
+// which is left transparent.
+pub fn rasterize(
+    map: &[u8],
+    map_width: usize,
+    map_height: usize,
+    tileset: &Tileset,
+    window_width: usize,
+    window_height: usize,
+) -> Vec<u8> {
+    rasterize_shaded(
+        map,
+        map_width,
+        map_height,
+        tileset,
+        &Shading::default(),
+        window_width,
+        window_height,
+    )
+}
+
+pub fn rasterize_shaded(
+    map: &[u8],
+    map_width: usize,
+    map_height: usize,
+    tileset: &Tileset,
+    shading: &Shading,
+    window_width: usize,
+    window_height: usize,
+) -> Vec<u8> {
+    let mut pixels = vec![0; window_width * window_height * 4];
+    let min_size = window_width.min(window_height) as f64;
+    let view = shading.view;
+    let shading = Shading {
+        nearest: shading.nearest || view.crisp(min_size, map_width, tileset.width),
+        cell_pixels: (
+            min_size * view.zoom / map_width as f64,
+            min_size * view.zoom / map_height as f64,
+        ),
+        ..*shading
+    };
+    for row in 0..window_height {
+        for column in 0..window_width {
+            // gl_FragCoord counts rows from the bottom.
+            let frag_coord = (column as f64 + 0.5, (window_height - row) as f64 - 0.5);
+            let pre_out_position = (
+                (frag_coord.0 - window_width as f64 * 0.5 + min_size * 0.5) / min_size,
+                (frag_coord.1 - window_height as f64 * 0.5 + min_size * 0.5) / min_size,
+            );
+            let out_position = view.to_map((pre_out_position.0, 1. - pre_out_position.1));
+            // Bounds as the unflipped position, which is what the shader checks.
+            if !(0. ..1.).contains(&out_position.0) || !(0. ..1.).contains(&(1. - out_position.1)) {
+                continue;
+            }
+            let color = shade(map, map_width, map_height, tileset, &shading, out_position);
+            let offset = (row * window_width + column) * 4;
+            pixels[offset..offset + 4].copy_from_slice(&color);
+        }
+    }
+    pixels
+}
+
+// Paints every cell of `map` with `scale` x `scale` pixels, shaded as above
+// but without letterboxing, so cells stay square whatever the map's shape.
+pub fn composite(
+    map: &[u8],
+    map_width: usize,
+    map_height: usize,
+    tileset: &Tileset,
+    scale: usize,
+) -> Vec<u8> {
+    let (width, height) = (map_width * scale, map_height * scale);
+    let mut pixels = vec![0; width * height * 4];
+    for row in 0..height {
+        for column in 0..width {
+            let out_position = (
+                (column as f64 + 0.5) / width as f64,
+                (row as f64 + 0.5) / height as f64,
+            );
+            let shading = Shading::default();
+            let color = shade(map, map_width, map_height, tileset, &shading, out_position);
+            let offset = (row * width + column) * 4;
+            pixels[offset..offset + 4].copy_from_slice(&color);
+        }
+    }
+    pixels
+}
+
+// Renders on the CPU into `pixels`, for tests, thumbnails and anything else
+// without a canvas. Draws the same image as `WebGl2Renderer` would.
+#[derive(Default)]
+pub struct HeadlessRenderer {
+    tileset: Option<Tileset>,
+    map: Vec<u8>,
+    map_width: usize,
+    map_height: usize,
+    masks: Vec<u8>,
+    mode: DisplayMode,
+    entropy_overlay: bool,
+    view: View,
+    pixel_art: bool,
+    highlights: Highlights,
+    transition: Transition,
+    collapse_times: CollapseTimes,
+    now: f64,
+    window_size: (u32, u32),
+    // RGBA, rows from the top, as of the last `draw`.
+    pub pixels: Vec<u8>,
+}
+
+impl HeadlessRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Renderer for HeadlessRenderer {
+    fn set_tileset(&mut self, tileset: &Tileset) -> Result<(), Error> {
+        self.tileset = Some(tileset.clone());
+        Ok(())
+    }
+
+    fn update_cells(&mut self, context: &Context, cells: Option<&[usize]>) -> Result<(), Error> {
+        match cells {
+            Some(cells) if self.map.len() == context.map.len() => {
+                for &position in cells {
+                    self.map[position] = context.map[position];
+                }
+            }
+            _ => {
+                self.map = context.map.clone();
+                self.map_width = context.map_width;
+                self.map_height = context.map_height;
+            }
+        }
+        if self.mode != DisplayMode::Plain || self.entropy_overlay {
+            self.masks = context.option_masks();
+        }
+        self.collapse_times.update(&context.map, cells, self.now);
+        Ok(())
+    }
+
+    fn draw(&mut self) -> Result<(), Error> {
+        let (width, height) = (self.window_size.0 as usize, self.window_size.1 as usize);
+        self.pixels = match self.tileset.as_ref() {
+            Some(tileset) if !self.map.is_empty() => rasterize_shaded(
+                &self.map,
+                self.map_width,
+                self.map_height,
+                tileset,
+                &Shading {
+                    masks: &self.masks,
+                    mode: self.mode,
+                    entropy_overlay: self.entropy_overlay,
+                    view: self.view,
+                    nearest: self.pixel_art,
+                    highlights: self.highlights,
+                    cell_pixels: (0., 0.),
+                    collapse_times: &self.collapse_times.times,
+                    transition: self.transition,
+                    now: self.now,
+                },
+                width,
+                height,
+            ),
+            _ => vec![0; width * height * 4],
+        };
+        Ok(())
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        self.window_size = (width, height);
+    }
+
+    fn set_display_mode(&mut self, mode: DisplayMode) {
+        self.mode = mode;
+    }
+
+    fn set_entropy_overlay(&mut self, enabled: bool) {
+        self.entropy_overlay = enabled;
+    }
+
+    fn set_view(&mut self, view: View) {
+        self.view = view;
+    }
+
+    fn set_pixel_art(&mut self, enabled: bool) {
+        self.pixel_art = enabled;
+    }
+
+    fn set_highlights(&mut self, highlights: &Highlights) {
+        self.highlights = *highlights;
+    }
+
+    fn set_transition(&mut self, transition: Transition) {
+        self.transition = transition;
+    }
+
+    fn set_clock(&mut self, now: f64) {
+        self.now = now;
+    }
+}
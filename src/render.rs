@@ -1,11 +1,227 @@
-use super::update::Context;
-use web_sys::{WebGl2RenderingContext, WebGlProgram, WebGlShader};
+use crate::error::Error;
+use crate::highlight::Highlights;
+use crate::log_at;
+use crate::renderer::{DisplayMode, Renderer};
+use crate::tileset::Tileset;
+use crate::transition::{CollapseTimes, Transition};
+use crate::update::Context;
+use crate::view::View;
+use js_sys::Float32Array;
+use web_sys::{
+    WebGl2RenderingContext, WebGlProgram, WebGlShader, WebGlTexture, WebGlUniformLocation,
+};
 
-impl Context {
-    pub fn get_program(webgl_context: &WebGl2RenderingContext) -> Result<WebGlProgram, String> {
+// Draws the map with a fragment shader that looks every pixel up in a map
+// texture of `rotation + 1` values, and samples the tile image accordingly.
+pub struct WebGl2Renderer {
+    webgl_context: WebGl2RenderingContext,
+    program: WebGlProgram,
+    texture_uniform_index: Option<WebGlUniformLocation>,
+    map_uniform_index: Option<WebGlUniformLocation>,
+    window_size_uniform_index: Option<WebGlUniformLocation>,
+    texture: Option<WebGlTexture>,
+    map_texture: Option<WebGlTexture>,
+    mask_texture: Option<WebGlTexture>,
+    masks_uniform_index: Option<WebGlUniformLocation>,
+    display_mode_uniform_index: Option<WebGlUniformLocation>,
+    display_mode: DisplayMode,
+    entropy_overlay_uniform_index: Option<WebGlUniformLocation>,
+    entropy_overlay: bool,
+    zoom_uniform_index: Option<WebGlUniformLocation>,
+    view_offset_uniform_index: Option<WebGlUniformLocation>,
+    view: View,
+    pixel_art_uniform_index: Option<WebGlUniformLocation>,
+    pixel_art: bool,
+    grid_uniform_index: Option<WebGlUniformLocation>,
+    grid_color_uniform_index: Option<WebGlUniformLocation>,
+    highlight_cells_uniform_index: Option<WebGlUniformLocation>,
+    highlight_colors_uniform_index: Option<WebGlUniformLocation>,
+    highlights: Highlights,
+    // R32F, when each cell collapsed, see `CollapseTimes`.
+    times_texture: Option<WebGlTexture>,
+    collapse_times: CollapseTimes,
+    // The texture missed updates while transitions were off.
+    times_stale: bool,
+    collapse_times_uniform_index: Option<WebGlUniformLocation>,
+    now_uniform_index: Option<WebGlUniformLocation>,
+    transition_duration_uniform_index: Option<WebGlUniformLocation>,
+    transition_style_uniform_index: Option<WebGlUniformLocation>,
+    transition: Transition,
+    now: f64,
+    // Size of `map_texture`, a map of another size needs a new one.
+    map_size: (usize, usize),
+    window_size: (u32, u32),
+}
+
+impl WebGl2Renderer {
+    pub fn new(webgl_context: WebGl2RenderingContext) -> Result<Self, Error> {
+        let program = Self::get_program(&webgl_context)?;
+        // The map textures have a byte per cell, rows of any width are packed.
+        webgl_context.pixel_storei(WebGl2RenderingContext::UNPACK_ALIGNMENT, 1);
+        Ok(WebGl2Renderer {
+            map_uniform_index: webgl_context.get_uniform_location(&program, "map"),
+            texture_uniform_index: webgl_context.get_uniform_location(&program, "atlas"),
+            window_size_uniform_index: webgl_context.get_uniform_location(&program, "window_size"),
+            masks_uniform_index: webgl_context.get_uniform_location(&program, "masks"),
+            display_mode_uniform_index: webgl_context
+                .get_uniform_location(&program, "display_mode"),
+            entropy_overlay_uniform_index: webgl_context
+                .get_uniform_location(&program, "entropy_overlay"),
+            zoom_uniform_index: webgl_context.get_uniform_location(&program, "zoom"),
+            view_offset_uniform_index: webgl_context.get_uniform_location(&program, "view_offset"),
+            pixel_art_uniform_index: webgl_context.get_uniform_location(&program, "pixel_art"),
+            grid_uniform_index: webgl_context.get_uniform_location(&program, "grid"),
+            grid_color_uniform_index: webgl_context.get_uniform_location(&program, "grid_color"),
+            highlight_cells_uniform_index: webgl_context
+                .get_uniform_location(&program, "highlight_cells"),
+            highlight_colors_uniform_index: webgl_context
+                .get_uniform_location(&program, "highlight_colors"),
+            collapse_times_uniform_index: webgl_context
+                .get_uniform_location(&program, "collapse_times"),
+            now_uniform_index: webgl_context.get_uniform_location(&program, "now"),
+            transition_duration_uniform_index: webgl_context
+                .get_uniform_location(&program, "transition_duration"),
+            transition_style_uniform_index: webgl_context
+                .get_uniform_location(&program, "transition_style"),
+            program,
+            texture: None,
+            map_texture: None,
+            mask_texture: None,
+            display_mode: DisplayMode::Plain,
+            entropy_overlay: false,
+            view: View::default(),
+            pixel_art: false,
+            highlights: Highlights::default(),
+            times_texture: None,
+            collapse_times: CollapseTimes::default(),
+            times_stale: true,
+            transition: Transition::default(),
+            now: 0.,
+            map_size: (0, 0),
+            window_size: (0, 0),
+            webgl_context,
+        })
+    }
+
+    fn create_texture(&self, filter: u32, what: &str) -> Result<WebGlTexture, Error> {
+        let webgl_context = &self.webgl_context;
+        let texture = webgl_context
+            .create_texture()
+            .ok_or_else(|| Error::Render(format!("could not create the {} texture", what)))?;
+        webgl_context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
+        webgl_context.tex_parameteri(
+            WebGl2RenderingContext::TEXTURE_2D,
+            WebGl2RenderingContext::TEXTURE_MAG_FILTER,
+            filter as i32,
+        );
+        webgl_context.tex_parameteri(
+            WebGl2RenderingContext::TEXTURE_2D,
+            WebGl2RenderingContext::TEXTURE_MIN_FILTER,
+            filter as i32,
+        );
+        Ok(texture)
+    }
+
+    // Mipmaps keep zoomed out maps from shimmering, pixel art keeps to whole
+    // texels of the nearest level instead.
+    fn set_atlas_filters(&self) {
+        if self.texture.is_none() {
+            return;
+        }
+        let (min_filter, mag_filter) = if self.pixel_art {
+            (
+                WebGl2RenderingContext::NEAREST_MIPMAP_NEAREST,
+                WebGl2RenderingContext::NEAREST,
+            )
+        } else {
+            (
+                WebGl2RenderingContext::LINEAR_MIPMAP_LINEAR,
+                WebGl2RenderingContext::LINEAR,
+            )
+        };
+        let webgl_context = &self.webgl_context;
+        webgl_context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, self.texture.as_ref());
+        webgl_context.tex_parameteri(
+            WebGl2RenderingContext::TEXTURE_2D,
+            WebGl2RenderingContext::TEXTURE_MIN_FILTER,
+            min_filter as i32,
+        );
+        webgl_context.tex_parameteri(
+            WebGl2RenderingContext::TEXTURE_2D,
+            WebGl2RenderingContext::TEXTURE_MAG_FILTER,
+            mag_filter as i32,
+        );
+    }
+
+    // Fills a whole map sized R8UI texture.
+    fn upload(&self, texture: Option<&WebGlTexture>, data: &[u8]) -> Result<(), Error> {
+        self.webgl_context
+            .bind_texture(WebGl2RenderingContext::TEXTURE_2D, texture);
+        self.webgl_context
+            .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+                WebGl2RenderingContext::TEXTURE_2D,
+                0,
+                WebGl2RenderingContext::R8UI as i32,
+                self.map_size.0 as i32,
+                self.map_size.1 as i32,
+                0,
+                WebGl2RenderingContext::RED_INTEGER,
+                WebGl2RenderingContext::UNSIGNED_BYTE,
+                Some(data),
+            )
+            .map_err(Error::render)
+    }
+
+    // The whole collapse times texture, or only `cells` of it.
+    fn upload_times(&self, cells: Option<&[usize]>) -> Result<(), Error> {
+        let webgl_context = &self.webgl_context;
+        let times = &self.collapse_times.times;
+        webgl_context.bind_texture(
+            WebGl2RenderingContext::TEXTURE_2D,
+            self.times_texture.as_ref(),
+        );
+        let cells = match cells {
+            Some(cells) => cells,
+            None => {
+                return webgl_context
+                    .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view(
+                        WebGl2RenderingContext::TEXTURE_2D,
+                        0,
+                        WebGl2RenderingContext::R32F as i32,
+                        self.map_size.0 as i32,
+                        self.map_size.1 as i32,
+                        0,
+                        WebGl2RenderingContext::RED,
+                        WebGl2RenderingContext::FLOAT,
+                        Some(&Float32Array::from(&times[..])),
+                    )
+                    .map_err(Error::render);
+            }
+        };
+        for &position in cells {
+            webgl_context
+                .tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_array_buffer_view(
+                    WebGl2RenderingContext::TEXTURE_2D,
+                    0,
+                    (position % self.map_size.0) as i32,
+                    (position / self.map_size.0) as i32,
+                    1,
+                    1,
+                    WebGl2RenderingContext::RED,
+                    WebGl2RenderingContext::FLOAT,
+                    Some(&Float32Array::from(&times[position..position + 1])),
+                )
+                .map_err(Error::render)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_program(webgl_context: &WebGl2RenderingContext) -> Result<WebGlProgram, Error> {
         let pixel_shader = Self::get_pixel_shader(webgl_context)?;
         let vertex_shader = Self::get_vertex_shader(webgl_context)?;
-        let program = webgl_context.create_program().unwrap();
+        let program = webgl_context
+            .create_program()
+            .ok_or_else(|| Error::Render("could not create a program".to_string()))?;
         webgl_context.attach_shader(&program, &pixel_shader);
         webgl_context.attach_shader(&program, &vertex_shader);
         webgl_context.link_program(&program);
@@ -16,13 +232,15 @@ impl Context {
         {
             Ok(program)
         } else {
-            Err(webgl_context
-                .get_program_info_log(&program)
-                .unwrap_or(String::from("walla lo yodea")))
+            Err(Error::ShaderLink(
+                webgl_context
+                    .get_program_info_log(&program)
+                    .unwrap_or_default(),
+            ))
         }
     }
 
-    pub fn get_pixel_shader(webgl_context: &WebGl2RenderingContext) -> Result<WebGlShader, String> {
+    pub fn get_pixel_shader(webgl_context: &WebGl2RenderingContext) -> Result<WebGlShader, Error> {
         Self::compile_shader(
             webgl_context,
             WebGl2RenderingContext::FRAGMENT_SHADER,
@@ -31,42 +249,173 @@ impl Context {
         uniform mediump sampler2D atlas;
         uniform mediump usampler2D map;
         uniform mediump uvec2 window_size;
+        // Bit per rotation still possible, for undecided cells.
+        uniform mediump usampler2D masks;
+        // 0 plain, 1 superposition, 2 domain size.
+        uniform uint display_mode;
+        // Tint undecided cells by their entropy, see `raster::entropy_color`.
+        uniform bool entropy_overlay;
+        // See `View`, the offset has y going down the map rows.
+        uniform float zoom;
+        uniform vec2 view_offset;
+        // Nearest texel sampling at any zoom.
+        uniform bool pixel_art;
+        // See `Highlights`, cells are map indices or -1 for none.
+        uniform bool grid;
+        uniform vec4 grid_color;
+        uniform int highlight_cells[4];
+        uniform vec4 highlight_colors[4];
+        // When each cell collapsed, against `now`, both in milliseconds. Cells
+        // come in over `transition_duration`, 0 fading and 1 scaling.
+        uniform highp sampler2D collapse_times;
+        uniform float now;
+        uniform float transition_duration;
+        uniform uint transition_style;
         out vec4 outColor;
+        // Sample the atlas at the nearest texel, set up by `main`.
+        bool crisp;
+        // How the cell position changes between neighbouring pixels. `mod`
+        // jumps at cell borders, which would pick the smallest mip level
+        // there and draw seams, so the atlas is sampled with these instead.
+        vec2 cell_dx;
+        vec2 cell_dy;
+
+        uint count_options(uint mask) {
+            return (mask & uint(1)) + (mask >> 1 & uint(1)) + (mask >> 2 & uint(1)) + (mask >> 3 & uint(1));
+        }
+
+        // The tile turned a quarter counterclockwise per rotation, as a
+        // corner plus a direction so derivatives can be turned the same way.
+        vec2 turn(uint rotation, vec2 v) {
+            if (rotation == uint(1)) {
+                return vec2(-v.y, v.x);
+            } else if (rotation == uint(2)) {
+                return -v;
+            } else if (rotation == uint(3)) {
+                return vec2(v.y, -v.x);
+            }
+            return v;
+        }
+
+        // `scale` cells to the tile, for the superposition grid.
+        vec4 tile(uint rotation, vec2 position, float scale) {
+            vec2 corners[4] = vec2[4](vec2(0,0), vec2(1,0), vec2(1,1), vec2(0,1));
+            vec2 atlas_position = corners[rotation] + turn(rotation, position);
+            vec2 atlas_size = vec2(textureSize(atlas, 0));
+            if (crisp) {
+                atlas_position = (floor(atlas_position * atlas_size) + 0.5) / atlas_size;
+            }
+            // Linear filtering stays within the tile instead of blending in
+            // what is across its edge.
+            atlas_position = clamp(atlas_position, 0.5 / atlas_size, 1. - 0.5 / atlas_size);
+            return textureGrad(atlas, atlas_position, turn(rotation, cell_dx * scale), turn(rotation, cell_dy * scale));
+        }
+
+        vec4 undecided(uint mask, vec2 position) {
+            vec4 background = vec4(0.9,0.9,0.9,1.);
+            if (display_mode == uint(1)) {
+                // A 2x2 grid of the rotations, faded, grey where ruled out.
+                uvec2 quadrant = uvec2(position * 2.);
+                uint rotation = quadrant.x + quadrant.y * uint(2);
+                if ((mask >> rotation & uint(1)) == uint(0)) {
+                    return vec4(0.8,0.8,0.8,1.);
+                }
+                return mix(background, tile(rotation, fract(position * 2.), 2.), 0.6);
+            } else if (display_mode == uint(2)) {
+                uint count = count_options(mask);
+                if (count == uint(0)) {
+                    return vec4(0,0,0,1);
+                }
+                float t = float(count - uint(1)) / 3.;
+                return vec4(mix(vec3(1.,0.85,0.3), vec3(0.25,0.35,0.8), t), 1.);
+            }
+            return background;
+        }
+
+        vec4 entropy(uint mask, vec4 under) {
+            uint count = count_options(mask);
+            if (count == uint(0)) {
+                return vec4(0.9,0.,0.59,1.);
+            }
+            float t = log2(float(count)) / 2.;
+            vec3 color = mix(vec3(0.9,0.2,0.1), vec3(0.2,0.45,0.95), t);
+            return vec4(mix(under.rgb, color, 0.55), 1.);
+        }
+
+        // `raster::collapsed`, a decided cell on its way in.
+        vec4 collapsed(uint rotation, vec2 position, vec2 cell_position) {
+            if (transition_duration <= 0.) {
+                return tile(rotation, position, 1.);
+            }
+            float since = now - texture(collapse_times, cell_position).x;
+            float progress = smoothstep(0., 1., clamp(since / transition_duration, 0., 1.));
+            vec4 background = vec4(0.9,0.9,0.9,1.);
+            if (transition_style == uint(1)) {
+                vec2 scaled = (position - 0.5) / progress + 0.5;
+                if (progress <= 0. || any(lessThan(scaled, vec2(0.))) || any(greaterThanEqual(scaled, vec2(1.)))) {
+                    return background;
+                }
+                return tile(rotation, scaled, 1. / progress);
+            }
+            return mix(background, tile(rotation, position, 1.), progress);
+        }
+
+        // `Highlights::color_at`, blended over `under`.
+        vec4 outlined(vec4 under, int cell, vec2 position, vec2 cell_pixels) {
+            vec2 edge = min(position, 1. - position) * cell_pixels;
+            for (int i = 0; i < 4; i++) {
+                if (highlight_cells[i] == cell) {
+                    if (min(edge.x, edge.y) < 2.) {
+                        vec4 color = highlight_colors[i];
+                        return vec4(mix(under.rgb, color.rgb, color.a), max(under.a, color.a));
+                    }
+                    break;
+                }
+            }
+            vec2 far_edge = (1. - position) * cell_pixels;
+            if (grid && min(cell_pixels.x, cell_pixels.y) >= 4. && min(far_edge.x, far_edge.y) < 1.) {
+                return vec4(mix(under.rgb, grid_color.rgb, grid_color.a), max(under.a, grid_color.a));
+            }
+            return under;
+        }
+
         void main() {
             uint min_size = min(window_size.x, window_size.y);
             vec2 preOutPosition = (gl_FragCoord.xy - vec2(window_size)*0.5 + vec2(min_size)*0.5) / vec2(min_size);
-            if (any(greaterThanEqual(preOutPosition,vec2(1.0))) || any(lessThan(preOutPosition, vec2(0.)))) {
+            vec2 outPosition = vec2(preOutPosition.x, 1.0 - preOutPosition.y) / zoom + view_offset;
+            vec2 size = vec2(textureSize(map,0));
+            // Before any branching, derivatives are undefined past that.
+            cell_dx = dFdx(outPosition * size);
+            cell_dy = dFdy(outPosition * size);
+            vec2 mapPosition = vec2(outPosition.x, 1.0 - outPosition.y);
+            if (any(greaterThanEqual(mapPosition,vec2(1.0))) || any(lessThan(mapPosition, vec2(0.)))) {
                 outColor = vec4(0,0,0,0);
                 return;
             }
-            vec2 outPosition = vec2(preOutPosition.x, 1.0 - preOutPosition.y);
             uint map_entry = texture(map, outPosition).x;
-            if (map_entry == uint(0)) {
-                outColor = vec4(0.9,0.9,0.9,1.);
-                return;
-            }
-            vec2 size = vec2(textureSize(map,0));
             vec2 position = mod(outPosition.xy * size, 1.0);
-            uint rotation = (map_entry - uint(1))%uint(4);
-            if (rotation == uint(0)){
-                outColor = texture(atlas, position);
-            } else if (rotation == uint(1)) {
-                outColor = texture(atlas, vec2(1.-position.y, position.x));
-            }
-            else if (rotation == uint(2)) {
-                outColor = texture(atlas, vec2(1.-position.x, 1.-position.y));
-            }
-            else if (rotation == uint(3)) {
-                outColor = texture(atlas, vec2(position.y, 1.-position.x));
+            // Past a couple of pixels per texel zoomed in, see `View::crisp`.
+            float texel_pixels = float(min_size) * zoom / (size.x * float(textureSize(atlas, 0).x));
+            crisp = pixel_art || (zoom > 1. && texel_pixels >= 2.);
+            vec4 color;
+            if (map_entry == uint(0)) {
+                uint mask = texture(masks, outPosition).x;
+                color = undecided(mask, position);
+                if (entropy_overlay) {
+                    color = entropy(mask, color);
+                }
+            } else {
+                color = collapsed((map_entry - uint(1))%uint(4), position, outPosition);
             }
+            ivec2 cell = min(ivec2(outPosition * size), ivec2(size) - 1);
+            vec2 cell_pixels = float(min_size) * zoom / size;
+            outColor = outlined(color, cell.y * int(size.x) + cell.x, position, cell_pixels);
         }
         "##,
         )
     }
 
-    pub fn get_vertex_shader(
-        webgl_context: &WebGl2RenderingContext,
-    ) -> Result<WebGlShader, String> {
+    pub fn get_vertex_shader(webgl_context: &WebGl2RenderingContext) -> Result<WebGlShader, Error> {
         Self::compile_shader(
             webgl_context,
             WebGl2RenderingContext::VERTEX_SHADER,
@@ -85,8 +434,10 @@ impl Context {
         webgl_context: &WebGl2RenderingContext,
         shader_type: u32,
         shader_str: &str,
-    ) -> Result<WebGlShader, String> {
-        let shader = webgl_context.create_shader(shader_type).unwrap();
+    ) -> Result<WebGlShader, Error> {
+        let shader = webgl_context
+            .create_shader(shader_type)
+            .ok_or_else(|| Error::Render("could not create a shader".to_string()))?;
         webgl_context.shader_source(&shader, shader_str);
         webgl_context.compile_shader(&shader);
         if webgl_context
@@ -96,9 +447,216 @@ impl Context {
         {
             Ok(shader)
         } else {
-            Err(webgl_context
-                .get_shader_info_log(&shader)
-                .unwrap_or_else(|| String::from("unknown error creating shader")))
+            Err(Error::ShaderCompile(
+                webgl_context
+                    .get_shader_info_log(&shader)
+                    .unwrap_or_default(),
+            ))
+        }
+    }
+}
+
+impl Renderer for WebGl2Renderer {
+    fn set_tileset(&mut self, tileset: &Tileset) -> Result<(), Error> {
+        log_at!(Debug, Render, "uploading the atlas");
+        let texture = self.create_texture(WebGl2RenderingContext::LINEAR, "atlas")?;
+        let webgl_context = &self.webgl_context;
+        webgl_context.tex_parameteri(
+            WebGl2RenderingContext::TEXTURE_2D,
+            WebGl2RenderingContext::TEXTURE_WRAP_S,
+            WebGl2RenderingContext::CLAMP_TO_EDGE as i32,
+        );
+        webgl_context.tex_parameteri(
+            WebGl2RenderingContext::TEXTURE_2D,
+            WebGl2RenderingContext::TEXTURE_WRAP_T,
+            WebGl2RenderingContext::CLAMP_TO_EDGE as i32,
+        );
+        webgl_context
+            .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+                WebGl2RenderingContext::TEXTURE_2D,
+                0,
+                WebGl2RenderingContext::RGBA as i32,
+                tileset.width as i32,
+                tileset.height as i32,
+                0,
+                WebGl2RenderingContext::RGBA,
+                WebGl2RenderingContext::UNSIGNED_BYTE,
+                Some(&tileset.data[..]),
+            )
+            .map_err(Error::render)?;
+        webgl_context.generate_mipmap(WebGl2RenderingContext::TEXTURE_2D);
+        self.texture = Some(texture);
+        self.set_atlas_filters();
+        Ok(())
+    }
+
+    fn update_cells(&mut self, context: &Context, cells: Option<&[usize]>) -> Result<(), Error> {
+        let map_size = (context.map_width, context.map_height);
+        let mut cells = cells;
+        if self.map_size != map_size || self.map_texture.is_none() {
+            self.map_texture = Some(self.create_texture(WebGl2RenderingContext::NEAREST, "map")?);
+            self.mask_texture = Some(self.create_texture(WebGl2RenderingContext::NEAREST, "mask")?);
+            self.times_texture =
+                Some(self.create_texture(WebGl2RenderingContext::NEAREST, "collapse times")?);
+            self.times_stale = true;
+            self.map_size = map_size;
+            self.upload(self.mask_texture.as_ref(), &vec![0; context.map.len()])?;
+            cells = None;
+        }
+        match cells {
+            None => {
+                log_at!(Debug, Render, "uploading the whole map");
+                self.upload(self.map_texture.as_ref(), &context.map)?;
+            }
+            Some(cells) => {
+                self.webgl_context.bind_texture(
+                    WebGl2RenderingContext::TEXTURE_2D,
+                    self.map_texture.as_ref(),
+                );
+                for &position in cells {
+                    self.webgl_context
+                        .tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_u8_array(
+                            WebGl2RenderingContext::TEXTURE_2D,
+                            0,
+                            (position % context.map_width) as i32,
+                            (position / context.map_width) as i32,
+                            1,
+                            1,
+                            WebGl2RenderingContext::RED_INTEGER,
+                            WebGl2RenderingContext::UNSIGNED_BYTE,
+                            Some(&context.map[position..]),
+                        )
+                        .map_err(Error::render)?;
+                }
+            }
+        }
+        // Propagation changes options all over the map, not only in the cells
+        // that got written, so the masks go over whole every time.
+        if self.display_mode != DisplayMode::Plain || self.entropy_overlay {
+            self.upload(self.mask_texture.as_ref(), &context.option_masks())?;
+        }
+        let collapsed = self.collapse_times.update(&context.map, cells, self.now);
+        if self.transition.duration > 0. {
+            match collapsed {
+                Some(collapsed) if !self.times_stale => self.upload_times(Some(&collapsed))?,
+                _ => self.upload_times(None)?,
+            }
+            self.times_stale = false;
+        } else {
+            self.times_stale = true;
         }
+        Ok(())
+    }
+
+    fn draw(&mut self) -> Result<(), Error> {
+        if self.texture.is_none() || self.map_texture.is_none() {
+            return Ok(());
+        }
+        let webgl_context = &self.webgl_context;
+        // The canvas may have been resized since the context was made.
+        webgl_context.viewport(0, 0, self.window_size.0 as i32, self.window_size.1 as i32);
+        webgl_context.use_program(Some(&self.program));
+        webgl_context.uniform1i(self.map_uniform_index.as_ref(), 1);
+        webgl_context.uniform1i(self.texture_uniform_index.as_ref(), 0);
+        webgl_context.uniform1i(self.masks_uniform_index.as_ref(), 2);
+        webgl_context.uniform1i(self.collapse_times_uniform_index.as_ref(), 3);
+        webgl_context.uniform1f(self.now_uniform_index.as_ref(), self.now as f32);
+        webgl_context.uniform1f(
+            self.transition_duration_uniform_index.as_ref(),
+            self.transition.duration as f32,
+        );
+        webgl_context.uniform1ui(
+            self.transition_style_uniform_index.as_ref(),
+            self.transition.style as u32,
+        );
+        webgl_context.uniform1ui(
+            self.display_mode_uniform_index.as_ref(),
+            self.display_mode as u32,
+        );
+        webgl_context.uniform1i(
+            self.entropy_overlay_uniform_index.as_ref(),
+            self.entropy_overlay as i32,
+        );
+        webgl_context.uniform1f(self.zoom_uniform_index.as_ref(), self.view.zoom as f32);
+        webgl_context.uniform1i(self.pixel_art_uniform_index.as_ref(), self.pixel_art as i32);
+        let highlights = &self.highlights;
+        let color = |color: [u8; 4]| color.map(|channel| channel as f32 / 255.);
+        webgl_context.uniform1i(self.grid_uniform_index.as_ref(), highlights.grid as i32);
+        webgl_context.uniform4fv_with_f32_array(
+            self.grid_color_uniform_index.as_ref(),
+            &color(highlights.grid_color),
+        );
+        let cells: Vec<i32> = highlights
+            .cells
+            .iter()
+            .map(|cell| cell.map_or(-1, |cell| cell as i32))
+            .collect();
+        webgl_context
+            .uniform1iv_with_i32_array(self.highlight_cells_uniform_index.as_ref(), &cells);
+        let colors: Vec<f32> = highlights.colors.iter().flat_map(|&c| color(c)).collect();
+        webgl_context
+            .uniform4fv_with_f32_array(self.highlight_colors_uniform_index.as_ref(), &colors);
+        webgl_context.uniform2f(
+            self.view_offset_uniform_index.as_ref(),
+            self.view.offset.0 as f32,
+            self.view.offset.1 as f32,
+        );
+        webgl_context.uniform2ui(
+            self.window_size_uniform_index.as_ref(),
+            self.window_size.0,
+            self.window_size.1,
+        );
+        webgl_context.active_texture(WebGl2RenderingContext::TEXTURE0);
+        webgl_context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, self.texture.as_ref());
+        webgl_context.active_texture(WebGl2RenderingContext::TEXTURE1);
+        webgl_context.bind_texture(
+            WebGl2RenderingContext::TEXTURE_2D,
+            self.map_texture.as_ref(),
+        );
+        webgl_context.active_texture(WebGl2RenderingContext::TEXTURE2);
+        webgl_context.bind_texture(
+            WebGl2RenderingContext::TEXTURE_2D,
+            self.mask_texture.as_ref(),
+        );
+        webgl_context.active_texture(WebGl2RenderingContext::TEXTURE3);
+        webgl_context.bind_texture(
+            WebGl2RenderingContext::TEXTURE_2D,
+            self.times_texture.as_ref(),
+        );
+        webgl_context.draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
+        Ok(())
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        self.window_size = (width, height);
+    }
+
+    fn set_display_mode(&mut self, mode: DisplayMode) {
+        self.display_mode = mode;
+    }
+
+    fn set_entropy_overlay(&mut self, enabled: bool) {
+        self.entropy_overlay = enabled;
+    }
+
+    fn set_view(&mut self, view: View) {
+        self.view = view;
+    }
+
+    fn set_pixel_art(&mut self, enabled: bool) {
+        self.pixel_art = enabled;
+        self.set_atlas_filters();
+    }
+
+    fn set_highlights(&mut self, highlights: &Highlights) {
+        self.highlights = *highlights;
+    }
+
+    fn set_transition(&mut self, transition: Transition) {
+        self.transition = transition;
+    }
+
+    fn set_clock(&mut self, now: f64) {
+        self.now = now;
     }
 }
@@ -0,0 +1,145 @@
+use crate::canvas::Canvas;
+use crate::canvas2d::Canvas2dRenderer;
+use crate::error::Error;
+use crate::highlight::Highlights;
+use crate::raster::entropy_color;
+use crate::render::WebGl2Renderer;
+use crate::tileset::Tileset;
+use crate::transition::Transition;
+use crate::update::Context;
+use crate::view::View;
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::prelude::*;
+
+// Something that shows a `Context`'s map. Renderers keep their own copy of
+// what they show (textures, a canvas, pixels), brought up to date through
+// `update_cells` and put on screen by `draw`.
+pub trait Renderer {
+    // Takes the tile image, to be used from the next `draw` on.
+    fn set_tileset(&mut self, tileset: &Tileset) -> Result<(), Error>;
+    // The cells of `context.map` that changed, or None for all of it, which
+    // is also how a map of another size comes in.
+    fn update_cells(&mut self, context: &Context, cells: Option<&[usize]>) -> Result<(), Error>;
+    fn draw(&mut self) -> Result<(), Error>;
+    // Size of the target in pixels.
+    fn resize(&mut self, width: u32, height: u32);
+    // How undecided cells look. Modes other than `Plain` follow the options
+    // left in every cell, and so redraw all of them on each update.
+    fn set_display_mode(&mut self, mode: DisplayMode);
+    // Tints undecided cells by their entropy, over whatever the display mode
+    // drew. Like the display modes it follows the options of every cell.
+    fn set_entropy_overlay(&mut self, enabled: bool);
+    // The zoom and pan to draw with.
+    fn set_view(&mut self, view: View);
+    // Nearest texel sampling at any zoom, for pixel art tilesets.
+    fn set_pixel_art(&mut self, enabled: bool);
+    // The grid and the outlined cells.
+    fn set_highlights(&mut self, highlights: &Highlights);
+    // How cells come in once they collapse.
+    fn set_transition(&mut self, transition: Transition);
+    // Milliseconds on any clock that only goes forward, which collapse times
+    // are recorded and transitions played against.
+    fn set_clock(&mut self, now: f64);
+}
+
+// What to show for cells that are not decided yet.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum DisplayMode {
+    // Flat light grey.
+    #[default]
+    Plain,
+    // A 2x2 grid of the rotations still possible, faded.
+    Superposition,
+    // A color for how many rotations are left, black for none.
+    DomainSize,
+}
+
+impl DisplayMode {
+    pub fn from_name(name: &str) -> Result<DisplayMode, Error> {
+        match name {
+            "plain" => Ok(DisplayMode::Plain),
+            "superposition" => Ok(DisplayMode::Superposition),
+            "domain_size" => Ok(DisplayMode::DomainSize),
+            _ => Err(Error::invalid(
+                "display mode must be \"plain\", \"superposition\" or \"domain_size\"",
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DisplayMode::Plain => "plain",
+            DisplayMode::Superposition => "superposition",
+            DisplayMode::DomainSize => "domain_size",
+        }
+    }
+}
+
+// The colors of the entropy overlay, one entry per number of options left
+// in a cell: `{ options, entropy, color }` with the entropy in bits and the
+// color as CSS, for pages to build a legend from.
+#[wasm_bindgen]
+pub fn entropy_legend() -> Result<Array, JsValue> {
+    let legend = Array::new();
+    for options in 0..=4u32 {
+        let entry = Object::new();
+        let color = entropy_color(options);
+        Reflect::set(&entry, &"options".into(), &options.into())?;
+        if options > 0 {
+            Reflect::set(&entry, &"entropy".into(), &(options as f64).log2().into())?;
+        }
+        Reflect::set(
+            &entry,
+            &"color".into(),
+            &format!("rgb({}, {}, {})", color[0], color[1], color[2]).into(),
+        )?;
+        legend.push(&entry);
+    }
+    Ok(legend)
+}
+
+// Which renderer to draw with, from the `renderer` option: `webgl2`,
+// `canvas2d`, or `auto` for WebGL2 when the browser has it and Canvas2D
+// otherwise.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RendererKind {
+    Auto,
+    WebGl2,
+    Canvas2d,
+}
+
+// Only canvas elements have a 2d fallback, an `OffscreenCanvas` needs WebGL2.
+pub fn create_renderer(canvas: &Canvas, kind: RendererKind) -> Result<Box<dyn Renderer>, Error> {
+    let webgl = match kind {
+        RendererKind::Canvas2d => Err(Error::NoWebGl2),
+        _ => canvas.get_webgl2_context(),
+    };
+    match (webgl, canvas) {
+        (Ok(webgl_context), _) => Ok(Box::new(WebGl2Renderer::new(webgl_context)?)),
+        (Err(Error::NoWebGl2), Canvas::Html(canvas)) if kind != RendererKind::WebGl2 => {
+            Ok(Box::new(Canvas2dRenderer::new(canvas)?))
+        }
+        (Err(error), _) => Err(error),
+    }
+}
+
+// Brings `renderer` up to date with `context` and draws at `now`, see
+// `Renderer::set_clock`. `changed` are the cells written since the last call;
+// when the solver flagged the whole map as changed it all goes over instead.
+pub fn render(
+    renderer: &mut dyn Renderer,
+    context: &mut Context,
+    changed: &[usize],
+    (width, height): (u32, u32),
+    now: f64,
+) -> Result<(), Error> {
+    renderer.set_clock(now);
+    renderer.resize(width, height);
+    if context.map_dirty {
+        context.map_dirty = false;
+        renderer.update_cells(context, None)?;
+    } else {
+        renderer.update_cells(context, Some(changed))?;
+    }
+    renderer.draw()
+}
@@ -0,0 +1,64 @@
+use crate::config::get_number;
+use crate::error::Error;
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::prelude::*;
+
+// One collapse made by the solver, in the order they happened. Replaying the
+// same records on the same tileset and size makes the same choices, whatever
+// the seed or however the steps were spread over frames.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Record {
+    pub x: usize,
+    pub y: usize,
+    pub spin: usize,
+    pub entropy: f64,
+    // Options removed by propagating the choice.
+    pub propagated: usize,
+    // Backtracks it led to, 0 when it held.
+    pub backtracks: usize,
+}
+
+impl Record {
+    pub fn to_js(self) -> Result<Object, JsValue> {
+        let record = Object::new();
+        let set = |key: &str, value: f64| {
+            Reflect::set(&record, &JsValue::from_str(key), &JsValue::from(value))
+        };
+        set("x", self.x as f64)?;
+        set("y", self.y as f64)?;
+        set("rotation", self.spin as f64)?;
+        set("entropy", self.entropy)?;
+        set("propagated", self.propagated as f64)?;
+        set("backtracks", self.backtracks as f64)?;
+        Ok(record)
+    }
+
+    pub fn from_js(value: &JsValue) -> Result<Record, JsValue> {
+        let field = |key| -> Result<f64, JsValue> {
+            get_number(value, key)?
+                .ok_or_else(|| Error::invalid(format!("record is missing `{}`", key)).into())
+        };
+        Ok(Record {
+            x: field("x")? as usize,
+            y: field("y")? as usize,
+            spin: field("rotation")? as usize,
+            entropy: get_number(value, "entropy")?.unwrap_or(0.),
+            propagated: get_number(value, "propagated")?.unwrap_or(0.) as usize,
+            backtracks: get_number(value, "backtracks")?.unwrap_or(0.) as usize,
+        })
+    }
+}
+
+pub fn records_to_js(records: &[Record]) -> Result<Array, JsValue> {
+    records.iter().map(|record| record.to_js()).collect()
+}
+
+pub fn records_from_js(value: &JsValue) -> Result<Vec<Record>, JsValue> {
+    if !Array::is_array(value) {
+        return Err(Error::invalid("expected an array of decision records").into());
+    }
+    Array::from(value)
+        .iter()
+        .map(|record| Record::from_js(&record))
+        .collect()
+}
@@ -0,0 +1,224 @@
+use crate::error::Error;
+use crate::rng::Rng;
+use crate::update::{Change, Context, Decision, Status};
+use std::convert::TryInto;
+
+// Snapshots are a small binary format: this tag, then the fields below in
+// order, integers little endian. The tileset is not included, a snapshot is
+// restored onto a context that already has the one it was taken with.
+const MAGIC: &[u8; 4] = b"WFC2";
+
+impl Context {
+    // Everything needed to carry on solving from where this context is:
+    // options, map, pins, RNG state and the decision stack with its trail.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        put(&mut bytes, self.map_width as u32);
+        put(&mut bytes, self.map_height as u32);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&self.rng.state().to_le_bytes());
+        bytes.push(self.status as u8);
+        let (x, y) = self.contradiction.unwrap_or((u32::MAX as usize, 0));
+        put(&mut bytes, x as u32);
+        put(&mut bytes, y as u32);
+        put(&mut bytes, self.collapsed as u32);
+        put(&mut bytes, self.backtracks as u32);
+        put(&mut bytes, self.max_backtracks as u32);
+        for count in &self.counts {
+            bytes.extend_from_slice(&count.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.map);
+        bytes.extend_from_slice(&self.option_masks());
+        bytes.extend_from_slice(&self.pins);
+        put(&mut bytes, self.trail.len() as u32);
+        for change in &self.trail {
+            match *change {
+                Change::Removed { x, y, spin } => {
+                    bytes.push(0);
+                    put(&mut bytes, x as u32);
+                    put(&mut bytes, y as u32);
+                    bytes.push(spin as u8);
+                }
+                Change::Written(position) => {
+                    bytes.push(1);
+                    put(&mut bytes, position as u32);
+                }
+            }
+        }
+        put(&mut bytes, self.decisions.len() as u32);
+        for decision in &self.decisions {
+            put(&mut bytes, decision.x as u32);
+            put(&mut bytes, decision.y as u32);
+            bytes.push(decision.spin as u8);
+            put(&mut bytes, decision.trail_len as u32);
+        }
+        bytes
+    }
+
+    // Puts the solver back in the state `snapshot` saved, size included.
+    // Pending events, the decision log and any replay still under way are
+    // dropped, they belong to the run being replaced, and the whole map is
+    // marked for upload.
+    pub fn restore(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let mut reader = Reader { bytes, offset: 0 };
+        if reader.take(4)? != MAGIC {
+            return Err(bad("not a solver snapshot"));
+        }
+        let width = reader.u32()? as usize;
+        let height = reader.u32()? as usize;
+        let size = width
+            .checked_mul(height)
+            .filter(|&size| size > 0)
+            .ok_or_else(|| bad("bad map size in snapshot"))?;
+        let cell = |x: usize, y: usize| x < width && y < height;
+        let seed = reader.u64()?;
+        let rng = Rng::from_state(reader.u64()?);
+        let status = match reader.u8()? {
+            0 => Status::Running,
+            1 => Status::Done,
+            2 => Status::Contradiction,
+            _ => return Err(bad("bad status in snapshot")),
+        };
+        let contradiction = match (reader.u32()?, reader.u32()?) {
+            (u32::MAX, _) => None,
+            (x, y) if cell(x as usize, y as usize) => Some((x as usize, y as usize)),
+            _ => return Err(bad("contradiction off the map in snapshot")),
+        };
+        let collapsed = reader.u32()? as usize;
+        let backtracks = reader.u32()? as usize;
+        let max_backtracks = reader.u32()? as usize;
+        let mut counts = vec![];
+        for _ in 0..4 {
+            counts.push(reader.u64()?);
+        }
+        let map = reader.take(size)?.to_vec();
+        if map.iter().any(|&value| value > 4) {
+            return Err(bad("bad map value in snapshot"));
+        }
+        let options = reader
+            .take(size)?
+            .chunks(width.max(1))
+            .map(|row| {
+                row.iter()
+                    .map(|mask| (0..4).map(|spin| (mask >> spin) & 1 == 1).collect())
+                    .collect()
+            })
+            .collect();
+        let pins = reader.take(size)?.to_vec();
+        if pins.iter().any(|&pin| pin > 4) {
+            return Err(bad("bad pin in snapshot"));
+        }
+        let mut trail = vec![];
+        for _ in 0..reader.u32()? {
+            let change = match reader.u8()? {
+                0 => Change::Removed {
+                    x: reader.u32()? as usize,
+                    y: reader.u32()? as usize,
+                    spin: reader.u8()? as usize,
+                },
+                1 => Change::Written(reader.u32()? as usize),
+                _ => return Err(bad("bad trail entry in snapshot")),
+            };
+            let fits = match change {
+                Change::Removed { x, y, spin } => cell(x, y) && spin < 4,
+                Change::Written(position) => position < size && map[position] != 0,
+            };
+            if !fits {
+                return Err(bad("trail entry off the map in snapshot"));
+            }
+            trail.push(change);
+        }
+        let mut decisions = vec![];
+        for _ in 0..reader.u32()? {
+            let decision = Decision {
+                x: reader.u32()? as usize,
+                y: reader.u32()? as usize,
+                spin: reader.u8()? as usize,
+                trail_len: reader.u32()? as usize,
+            };
+            let after = decisions.last().map_or(0, |last: &Decision| last.trail_len);
+            if !cell(decision.x, decision.y)
+                || decision.spin >= 4
+                || !(after..=trail.len()).contains(&decision.trail_len)
+            {
+                return Err(bad("bad decision in snapshot"));
+            }
+            decisions.push(decision);
+        }
+        if reader.offset != bytes.len() {
+            return Err(bad("trailing bytes after snapshot"));
+        }
+        // Backtracking and undoing count these back down.
+        for (spin, &count) in counts.iter().enumerate() {
+            if count != decisions.iter().filter(|d| d.spin == spin).count() as u64 {
+                return Err(bad("counts do not match the decisions in snapshot"));
+            }
+        }
+        let written = trail
+            .iter()
+            .filter(|change| matches!(change, Change::Written(_)))
+            .count();
+        if collapsed != written || collapsed != map.iter().filter(|&&value| value != 0).count() {
+            return Err(bad("collapsed count does not match the map in snapshot"));
+        }
+
+        self.map_width = width;
+        self.map_height = height;
+        self.seed = seed;
+        self.rng = rng;
+        self.status = status;
+        self.contradiction = contradiction;
+        self.last_collapsed = None;
+        self.last_conflict = contradiction;
+        self.collapsed = collapsed;
+        self.backtracks = backtracks;
+        self.max_backtracks = max_backtracks;
+        self.counts = counts;
+        self.map = map;
+        self.options = options;
+        self.trail = trail;
+        self.decisions = decisions;
+        self.pins = pins;
+        self.events.clear();
+        self.decision_log.clear();
+        self.replay.clear();
+        self.map_dirty = true;
+        Ok(())
+    }
+}
+
+fn bad(message: &str) -> Error {
+    Error::BadSnapshot(message.to_string())
+}
+
+fn put(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], Error> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset.saturating_add(length))
+            .ok_or_else(|| bad("snapshot is truncated"))?;
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+
+use crate::error::Error;
+use web_sys::ImageData;
+
+// RGBA pixels of the tile image together with the hashes of its four borders,
+// which is all the solver needs to know about the tileset.
+#[derive(Clone)]
+pub struct Tileset {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+    pub borders_hash: Vec<u64>,
+}
+
+impl Tileset {
+    pub fn new(data: Vec<u8>, width: usize, height: usize) -> Result<Self, Error> {
+        if width == 0 || height == 0 {
+            return Err(Error::BadTileset("the image is empty".to_string()));
+        }
+        if data.len() != width * height * 4 {
+            return Err(Error::BadTileset(format!(
+                "expected {} bytes of RGBA pixels for {}x{}, got {}",
+                width * height * 4,
+                width,
+                height,
+                data.len()
+            )));
+        }
+        let mut tileset = Tileset {
+            width,
+            height,
+            data,
+            borders_hash: vec![],
+        };
+        for i in 0..4 {
+            let hash = tileset.get_border(i);
+            tileset.borders_hash.push(hash);
+        }
+        Ok(tileset)
+    }
+
+    pub fn from_image_data(image: &ImageData) -> Result<Self, Error> {
+        Self::new(
+            image.data().0,
+            image.width() as usize,
+            image.height() as usize,
+        )
+    }
+
+    fn get_border(&self, border: i32) -> u64 {
+        let mut hash = DefaultHasher::new();
+        match border {
+            0 => {
+                hash.write(self.data.chunks(self.width * 4).last().unwrap());
+            }
+            1 => {
+                self.data
+                    .chunks(4)
+                    .step_by(self.width)
+                    .for_each(|v| hash.write(v));
+            }
+            2 => {
+                hash.write(self.data.chunks(self.width * 4).next().unwrap());
+            }
+            3 => {
+                self.data
+                    .chunks(4)
+                    .skip(self.width - 1)
+                    .step_by(self.width)
+                    .for_each(|v| hash.write(v));
+            }
+            _ => return 0,
+        }
+        hash.finish()
+    }
+}
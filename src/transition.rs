@@ -0,0 +1,99 @@
+use crate::error::Error;
+
+// How newly collapsed cells come in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TransitionStyle {
+    // From the undecided grey to the tile.
+    #[default]
+    Fade,
+    // The tile growing from the middle of the cell.
+    Scale,
+}
+
+impl TransitionStyle {
+    pub fn from_name(name: &str) -> Result<TransitionStyle, Error> {
+        match name {
+            "fade" => Ok(TransitionStyle::Fade),
+            "scale" => Ok(TransitionStyle::Scale),
+            _ => Err(Error::invalid("transition must be \"fade\" or \"scale\"")),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TransitionStyle::Fade => "fade",
+            TransitionStyle::Scale => "scale",
+        }
+    }
+}
+
+// A `duration` of 0 milliseconds, the default, shows cells right away.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Transition {
+    pub duration: f64,
+    pub style: TransitionStyle,
+}
+
+impl Transition {
+    // Negative durations are taken as 0, anything not finite is refused.
+    pub fn set_duration(&mut self, duration: f64) -> Result<(), Error> {
+        if !duration.is_finite() {
+            return Err(Error::invalid(
+                "transition_duration must be a finite number",
+            ));
+        }
+        self.duration = duration.max(0.);
+        Ok(())
+    }
+
+    // From 0 when a cell collapses to 1 once it is fully shown, eased.
+    pub fn progress(&self, since: f64) -> f64 {
+        if self.duration <= 0. {
+            return 1.;
+        }
+        let t = (since / self.duration).clamp(0., 1.);
+        t * t * (3. - 2. * t)
+    }
+}
+
+// Long before any clock reading, for cells that were there from the start.
+pub const LONG_AGO: f32 = -1e9;
+
+// When each cell last collapsed, on the renderer's clock in milliseconds,
+// worked out from the map updates a renderer gets.
+#[derive(Default)]
+pub struct CollapseTimes {
+    map: Vec<u8>,
+    pub times: Vec<f32>,
+}
+
+impl CollapseTimes {
+    // Returns the cells that collapsed, or None when the map changed size and
+    // everything counts as long collapsed.
+    pub fn update(&mut self, map: &[u8], cells: Option<&[usize]>, now: f64) -> Option<Vec<usize>> {
+        if self.map.len() != map.len() {
+            self.map = map.to_vec();
+            self.times = vec![LONG_AGO; map.len()];
+            return None;
+        }
+        let mut collapsed = vec![];
+        let mut check = |position: usize| {
+            if map[position] != self.map[position] {
+                self.map[position] = map[position];
+                if map[position] != 0 {
+                    self.times[position] = now as f32;
+                    collapsed.push(position);
+                }
+            }
+        };
+        match cells {
+            Some(cells) => cells.iter().copied().for_each(&mut check),
+            None => (0..map.len()).for_each(&mut check),
+        }
+        Some(collapsed)
+    }
+
+    pub fn get(&self, position: usize) -> f64 {
+        self.times.get(position).map_or(LONG_AGO, |&time| time) as f64
+    }
+}
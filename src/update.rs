@@ -1,204 +1,516 @@
-use std::collections::HashSet;
-use std::hash::Hasher;
-use std::{collections::hash_map::DefaultHasher, iter::repeat};
+use std::collections::{BTreeSet, VecDeque};
+use std::iter::repeat;
 
-#[allow(unused)]
-use crate::log;
-use web_sys::{
-    ImageData, WebGl2RenderingContext, WebGlProgram, WebGlTexture, WebGlUniformLocation,
-};
+use crate::error::Error;
+use crate::events::Event;
+use crate::log_at;
+use crate::replay::Record;
+use crate::rng::Rng;
+use crate::tileset::Tileset;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Status {
+    Running,
+    Done,
+    Contradiction,
+}
+
+// Everything that has to be undone when backtracking over a decision.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Change {
+    Removed { x: usize, y: usize, spin: usize },
+    Written(usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Decision {
+    pub x: usize,
+    pub y: usize,
+    pub spin: usize,
+    pub trail_len: usize,
+}
 
 pub struct Context {
-    pub image: Option<ImageData>,
-    pub texture: Option<WebGlTexture>,
+    pub tileset: Option<Tileset>,
     pub map: Vec<u8>,
     pub map_height: usize,
     pub map_width: usize,
-    pub map_texture: Option<WebGlTexture>,
-    pub program: Option<WebGlProgram>,
-    pub texture_uniform_index: Option<WebGlUniformLocation>,
-    pub map_uniform_index: Option<WebGlUniformLocation>,
-    pub window_size_uniform_index: Option<WebGlUniformLocation>,
 
     pub cooldown_start: f64,
     pub counts: Vec<u64>,
     pub options: Vec<Vec<Vec<bool>>>,
     pub borders_hash: Vec<u64>,
+    pub seed: u64,
+    pub rng: Rng,
+    pub status: Status,
+    pub collapsed: usize,
+    pub trail: Vec<Change>,
+    pub decisions: Vec<Decision>,
+    pub contradiction: Option<(usize, usize)>,
+    // For highlighting: the cell written last, and where propagation last
+    // ran out of options, whether or not backtracking got past it.
+    pub last_collapsed: Option<usize>,
+    pub last_conflict: Option<(usize, usize)>,
+    pub backtracks: usize,
+    // Backtracking can take exponentially long on bad tilesets, past this many
+    // the run is given up as a contradiction.
+    pub max_backtracks: usize,
+    pub events: Vec<Event>,
+    // Every collapse so far, see `Record`. Pins are left out, they are kept
+    // in `pins` and hold through a replay anyway, and the log is not touched
+    // when a pin has the decisions made again.
+    pub decision_log: Vec<Record>,
+    // Collapses still to be replayed, made instead of picking by entropy.
+    pub replay: VecDeque<Record>,
+    // Set when `map` changed in more than one cell, so the renderer sends it
+    // all over again.
+    pub map_dirty: bool,
+    // Cells fixed by hand, as in `map`, 0 for the ones left to the solver.
+    // They hold through resets and are never backtracked over.
+    pub pins: Vec<u8>,
 }
 
 impl Context {
-    pub fn new() -> Self {
-        let width = 60;
-        let height = 60;
+    pub fn new(width: usize, height: usize) -> Self {
         Context {
-            image: None,
-            texture: None,
-            map: vec![],
+            tileset: None,
+            map: vec![0; height * width],
             map_height: height,
             map_width: width,
-            map_texture: None,
-            program: None,
-            map_uniform_index: None,
-            texture_uniform_index: None,
-            window_size_uniform_index: None,
 
             cooldown_start: 0.,
             counts: vec![0; 4],
             options: vec![vec![vec![true; 4]; width]; height],
             borders_hash: vec![],
+            seed: 0,
+            rng: Rng::new(0),
+            status: Status::Running,
+            collapsed: 0,
+            trail: vec![],
+            decisions: vec![],
+            contradiction: None,
+            last_collapsed: None,
+            last_conflict: None,
+            backtracks: 0,
+            max_backtracks: 1000,
+            events: vec![],
+            decision_log: vec![],
+            replay: VecDeque::new(),
+            map_dirty: true,
+            pins: vec![0; height * width],
         }
     }
 
-    pub fn set_image(&mut self, image: ImageData, webgl_context: &WebGl2RenderingContext) {
-        let texture = webgl_context.create_texture().unwrap();
-        webgl_context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
-        webgl_context.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
-            WebGl2RenderingContext::TEXTURE_MAG_FILTER,
-            WebGl2RenderingContext::LINEAR as i32,
-        );
-        webgl_context.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
-            WebGl2RenderingContext::TEXTURE_MIN_FILTER,
-            WebGl2RenderingContext::LINEAR as i32,
-        );
+    pub fn set_tileset(&mut self, tileset: Tileset) {
+        self.borders_hash = tileset.borders_hash.clone();
+        self.tileset = Some(tileset);
+        // Pins made before there was a tileset only propagate now.
+        if self.pins.iter().any(|&pin| pin != 0) {
+            self.rebuild();
+        }
+    }
 
-        webgl_context.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
-            WebGl2RenderingContext::TEXTURE_WRAP_S,
-            WebGl2RenderingContext::MIRRORED_REPEAT as i32,
-        );
-        webgl_context.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
-            WebGl2RenderingContext::TEXTURE_WRAP_T,
-            WebGl2RenderingContext::MIRRORED_REPEAT as i32,
-        );
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.reset();
+    }
 
-        webgl_context
-            .tex_image_2d_with_u32_and_u32_and_image_data(
-                WebGl2RenderingContext::TEXTURE_2D,
-                0,
-                WebGl2RenderingContext::RGBA as i32,
-                WebGl2RenderingContext::RGBA,
-                WebGl2RenderingContext::UNSIGNED_BYTE,
-                &image,
-            )
-            .unwrap();
-        self.image = Some(image.clone());
-        self.texture = Some(texture);
-
-        for i in 0..4 {
-            self.borders_hash.push(self.get_border_of_image(i));
-        }
+    pub fn reset(&mut self) {
+        self.rng = Rng::new(self.seed);
+        self.events.clear();
+        self.decision_log.clear();
+        self.replay.clear();
+        self.clear_cells();
+    }
 
+    // Back to every cell undecided but for the pins, propagated.
+    fn clear_cells(&mut self) {
         self.map = vec![0; self.map_height * self.map_width];
-        let map_texture = webgl_context.create_texture().unwrap();
-        webgl_context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&map_texture));
-        webgl_context.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
-            WebGl2RenderingContext::TEXTURE_MAG_FILTER,
-            WebGl2RenderingContext::NEAREST as i32,
-        );
-        webgl_context.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
-            WebGl2RenderingContext::TEXTURE_MIN_FILTER,
-            WebGl2RenderingContext::NEAREST as i32,
-        );
-        webgl_context
-            .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
-                WebGl2RenderingContext::TEXTURE_2D,
-                0,
-                WebGl2RenderingContext::R8UI as i32,
-                self.map_width as i32,
-                self.map_height as i32,
-                0,
-                WebGl2RenderingContext::RED_INTEGER,
-                WebGl2RenderingContext::UNSIGNED_BYTE,
-                Some(&self.map[0..]),
-            )
-            .unwrap();
-        self.map_texture = Some(map_texture);
+        self.counts = vec![0; 4];
+        self.options = vec![vec![vec![true; 4]; self.map_width]; self.map_height];
+        self.status = Status::Running;
+        self.collapsed = 0;
+        self.trail.clear();
+        self.decisions.clear();
+        self.contradiction = None;
+        self.last_collapsed = None;
+        self.last_conflict = None;
+        self.backtracks = 0;
+        self.map_dirty = true;
+        let pinned: Vec<usize> = (0..self.pins.len())
+            .filter(|&position| self.pins[position] != 0)
+            .collect();
+        for &position in &pinned {
+            let spin = self.pins[position] as usize - 1;
+            let cell = &mut self.options[position / self.map_width][position % self.map_width];
+            for (other, option) in cell.iter_mut().enumerate() {
+                *option = other == spin;
+            }
+        }
+        if self.borders_hash.is_empty() {
+            return;
+        }
+        for position in pinned {
+            let (x, y) = (position % self.map_width, position / self.map_width);
+            if let Err((x, y)) = self.branch_out(x, y) {
+                self.fail(x, y);
+                return;
+            }
+        }
+    }
+
+    // Pins the cell at `x`, `y` to `rotation`, or with None leaves it to the
+    // solver again, and carries on from there: the decisions made so far are
+    // made again on top of the pins, up to the first one that no longer fits.
+    pub fn pin(&mut self, x: usize, y: usize, rotation: Option<usize>) {
+        self.pins[y * self.map_width + x] = rotation.map_or(0, |rotation| rotation as u8 + 1);
+        // Whatever the solver had decided there goes either way.
+        self.decisions
+            .retain(|decision| (decision.x, decision.y) != (x, y));
+        self.rebuild();
+    }
+
+    // For pins handed in from JS.
+    pub fn check_pin(&self, x: usize, y: usize, rotation: Option<usize>) -> Result<(), Error> {
+        if x >= self.map_width || y >= self.map_height {
+            return Err(Error::invalid("cell out of the map"));
+        }
+        if rotation.is_some_and(|rotation| rotation >= 4) {
+            return Err(Error::invalid("rotation must be 0, 1, 2 or 3"));
+        }
+        Ok(())
+    }
+
+    pub fn clear_pins(&mut self) {
+        self.pins = vec![0; self.map_height * self.map_width];
+        self.rebuild();
+    }
+
+    fn rebuild(&mut self) {
+        let decisions = std::mem::take(&mut self.decisions);
+        self.clear_cells();
+        self.write_settled();
+        self.redo(decisions);
+        self.events.push(Event::Progress {
+            collapsed: self.collapsed,
+            total: self.map.len(),
+        });
+    }
+
+    // Makes `decisions` again in order, up to the first one that no longer
+    // fits.
+    fn redo(&mut self, decisions: Vec<Decision>) {
+        if self.status != Status::Running || self.borders_hash.is_empty() {
+            return;
+        }
+        for Decision { x, y, spin, .. } in decisions {
+            let options = &self.options[y][x];
+            if !options[spin] {
+                break;
+            }
+            // Already settled by the pins or the decisions before.
+            if options.iter().filter(|b| **b).count() < 2 {
+                continue;
+            }
+            let trail_len = self.trail.len();
+            self.decide(x, y, spin);
+            if self.branch_out(x, y).is_err() {
+                self.undo_to(trail_len);
+                self.decisions.pop();
+                self.counts[spin] -= 1;
+                break;
+            }
+            self.write_settled();
+        }
+    }
+
+    // All at once rather than a cell per step, so the map is shown as it was
+    // but for what the pins changed. Each write goes on the trail after the
+    // decision that settled the cell, for undoing that decision to take it
+    // back out.
+    fn write_settled(&mut self) {
+        for position in 0..self.map.len() {
+            let cell = &self.options[position / self.map_width][position % self.map_width];
+            if self.map[position] == 0 && cell.iter().filter(|b| **b).count() == 1 {
+                self.map[position] = cell.iter().position(|b| *b).unwrap() as u8 + 1;
+                self.trail.push(Change::Written(position));
+                self.collapsed += 1;
+            }
+        }
+    }
+
+    // Starts over, making the collapses of `records` in order before going
+    // back to picking cells by entropy. A record that is no longer possible
+    // ends the replay there.
+    pub fn start_replay(&mut self, records: Vec<Record>) {
+        self.reset();
+        self.replay = records.into();
+    }
+
+    // A bit per rotation still possible in each cell, row major.
+    pub fn option_masks(&self) -> Vec<u8> {
+        self.options
+            .iter()
+            .flatten()
+            .map(|cell| {
+                cell.iter()
+                    .enumerate()
+                    .fold(0, |mask, (spin, option)| mask | (*option as u8) << spin)
+            })
+            .collect()
+    }
+
+    // The other way around, for a copy of the solver kept in sync from
+    // `option_masks` sent by a worker.
+    pub fn set_option_masks(&mut self, masks: &[u8]) {
+        for (cell, mask) in self.options.iter_mut().flatten().zip(masks) {
+            for (spin, option) in cell.iter_mut().enumerate() {
+                *option = (mask >> spin) & 1 == 1;
+            }
+        }
     }
 
     pub fn update(&mut self, time: f64) -> Option<usize> {
         if time - self.cooldown_start > 100. / 60. * 1. {
-            // if time > 300. {
-            //     return None;
-            // }
-            log!("in");
-            for y in 0..self.options.len() {
-                for x in 0..self.options[0].len() {
-                    if self.map[y * self.map_width + x] == 0
-                        && self.options[y][x].iter().filter(|b| **b).count() == 1
-                    {
-                        let position = y * self.map_width + x;
-                        // log!("position {}", position);
-                        self.map[position] =
-                            self.options[y][x].iter().position(|b| *b).unwrap() as u8 + 1;
-                        return Some(position);
+            return self.step();
+        }
+        None
+    }
+
+    // Advances the solver by one step: either writes a cell that has been
+    // narrowed down to a single option into `map` and returns its position, or
+    // collapses the lowest entropy cell and propagates, returning None.
+    pub fn step(&mut self) -> Option<usize> {
+        if self.status != Status::Running || self.borders_hash.is_empty() {
+            return None;
+        }
+        for y in 0..self.options.len() {
+            for x in 0..self.options[0].len() {
+                if self.map[y * self.map_width + x] == 0
+                    && self.options[y][x].iter().filter(|b| **b).count() == 1
+                {
+                    let position = y * self.map_width + x;
+                    // log!("position {}", position);
+                    self.map[position] =
+                        self.options[y][x].iter().position(|b| *b).unwrap() as u8 + 1;
+                    log_at!(
+                        Trace,
+                        Solver,
+                        "wrote {} at ({}, {})",
+                        self.map[position],
+                        x,
+                        y
+                    );
+                    self.trail.push(Change::Written(position));
+                    self.collapsed += 1;
+                    self.last_collapsed = Some(position);
+                    self.events.push(Event::Progress {
+                        collapsed: self.collapsed,
+                        total: self.map.len(),
+                    });
+                    return Some(position);
+                }
+            }
+        }
+        let counts = &mut self.counts;
+        let rng = &mut self.rng;
+        let minimum_entropy = self
+            .options
+            .iter()
+            .enumerate()
+            .flat_map(|(y, v)| {
+                repeat(y).zip(
+                    v.iter()
+                        .enumerate()
+                        .filter(|(_x, v)| v.iter().filter(|b| **b).count() >= 2)
+                        .flat_map(move |(x, v)| {
+                            // if y == 0 && x == 0 {
+                            //     log!("y {}, x {}, {:?}", y, x, v);
+                            // }
+                            repeat(x).zip(v.iter().enumerate())
+                        }),
+                )
+            })
+            .filter_map(|(y, (x, (spin, b)))| if *b { Some((y, x, spin)) } else { None })
+            .map(|(y, x, spin)| {
+                counts[spin] += 1;
+                // A little noise breaks ties between equal candidates, which is
+                // where the seed comes into play.
+                let entropy = Self::calculate_entropy(counts) + rng.next_f64() * 1e-6;
+                counts[spin] -= 1;
+                (y, x, spin, entropy)
+            })
+            .fold(
+                (usize::MAX, usize::MAX, 0, -f64::MAX),
+                |(y_acc, x_acc, spin_acc, entropy_acc), (y, x, spin, entropy)| {
+                    // log!("entropy {:?}", entropy);
+                    if entropy > entropy_acc {
+                        (y, x, spin, entropy)
+                    } else {
+                        (y_acc, x_acc, spin_acc, entropy_acc)
                     }
+                },
+            );
+        if minimum_entropy.0 == usize::MAX {
+            let empty =
+                self.options.iter().enumerate().find_map(|(y, row)| {
+                    row.iter().position(|v| !v.contains(&true)).map(|x| (x, y))
+                });
+            match empty {
+                Some((x, y)) => self.fail(x, y),
+                None => {
+                    log_at!(Info, Solver, "done after {} backtracks", self.backtracks);
+                    self.status = Status::Done;
+                    self.events.push(Event::Complete);
                 }
             }
-            let counts = &mut self.counts;
-            let minimum_entropy = self
-                .options
-                .iter()
-                .enumerate()
-                .flat_map(|(y, v)| {
-                    repeat(y).zip(
-                        v.iter()
-                            .enumerate()
-                            .filter(|(_x, v)| v.iter().filter(|b| **b).count() >= 2)
-                            .flat_map(move |(x, v)| {
-                                // if y == 0 && x == 0 {
-                                //     log!("y {}, x {}, {:?}", y, x, v);
-                                // }
-                                repeat(x).zip(v.iter().enumerate())
-                            }),
-                    )
-                })
-                .filter_map(|(y, (x, (spin, b)))| if *b { Some((y, x, spin)) } else { None })
-                .map(|(y, x, spin)| {
-                    counts[spin] += 1;
-                    let entropy = Self::calculate_entropy(&counts);
-                    counts[spin] -= 1;
-                    (y, x, spin, entropy)
-                })
-                .fold(
-                    (usize::max_value(), usize::max_value(), 0, -f64::MAX),
-                    |(y_acc, x_acc, spin_acc, entropy_acc), (y, x, spin, entropy)| {
-                        // log!("entropy {:?}", entropy);
-                        if entropy > entropy_acc {
-                            (y, x, spin, entropy)
-                        } else {
-                            (y_acc, x_acc, spin_acc, entropy_acc)
-                        }
-                    },
-                );
-            if minimum_entropy.0 == usize::max_value() {
-                return None;
+            return None;
+        }
+        let (y, x, spin, entropy) = match self.replay.pop_front() {
+            Some(record)
+                if self
+                    .options
+                    .get(record.y)
+                    .and_then(|row| row.get(record.x))
+                    .and_then(|cell| cell.get(record.spin))
+                    == Some(&true)
+                    && self.map[record.y * self.map_width + record.x] == 0 =>
+            {
+                (record.y, record.x, record.spin, record.entropy)
             }
-            log!(
-                "options {:?}",
-                self.options[minimum_entropy.0][minimum_entropy.1]
+            Some(_) => {
+                self.replay.clear();
+                minimum_entropy
+            }
+            None => minimum_entropy,
+        };
+        log_at!(
+            Debug,
+            Solver,
+            "collapsing ({}, {}) to rotation {}, entropy {}",
+            x,
+            y,
+            spin,
+            entropy
+        );
+        self.decide(x, y, spin);
+        let (trail_len, backtracks) = (self.trail.len(), self.backtracks);
+        let propagated = self.branch_out(x, y);
+        let record = Record {
+            x,
+            y,
+            spin,
+            entropy,
+            propagated: self.trail.len() - trail_len,
+            backtracks: 0,
+        };
+        if let Err((x, y)) = propagated {
+            self.backtrack(x, y);
+        }
+        self.decision_log.push(Record {
+            backtracks: self.backtracks - backtracks,
+            ..record
+        });
+        None
+    }
+
+    // Collapses the cell at `x`, `y` to `spin` on the decision stack, leaving
+    // the propagation to the caller.
+    fn decide(&mut self, x: usize, y: usize, spin: usize) {
+        self.decisions.push(Decision {
+            x,
+            y,
+            spin,
+            trail_len: self.trail.len(),
+        });
+        for (other, option) in self.options[y][x].iter_mut().enumerate() {
+            if *option && other != spin {
+                *option = false;
+                self.trail.push(Change::Removed { x, y, spin: other });
+            }
+        }
+        self.counts[spin] += 1;
+    }
+
+    // Undoes decisions until the contradiction found at `x`, `y` goes away,
+    // banning each undone choice so it is not made again. Fails for good once
+    // there is nothing left to undo.
+    fn backtrack(&mut self, mut x: usize, mut y: usize) {
+        self.last_conflict = Some((x, y));
+        while self.backtracks < self.max_backtracks {
+            let decision = match self.decisions.pop() {
+                Some(decision) => decision,
+                None => break,
+            };
+            self.backtracks += 1;
+            log_at!(
+                Info,
+                Solver,
+                "contradiction at ({}, {}), undoing rotation {} at ({}, {})",
+                x,
+                y,
+                decision.spin,
+                decision.x,
+                decision.y
             );
-            log!("first_option {:?}", minimum_entropy);
-            for i in self.options[minimum_entropy.0][minimum_entropy.1].iter_mut() {
-                *i = false;
+            self.undo_to(decision.trail_len);
+            self.counts[decision.spin] -= 1;
+            self.events.push(Event::Backtrack {
+                x,
+                y,
+                depth: self.decisions.len(),
+            });
+            self.options[decision.y][decision.x][decision.spin] = false;
+            self.trail.push(Change::Removed {
+                x: decision.x,
+                y: decision.y,
+                spin: decision.spin,
+            });
+            match self.branch_out(decision.x, decision.y) {
+                Ok(()) => return,
+                Err(cell) => {
+                    x = cell.0;
+                    y = cell.1;
+                }
+            }
+        }
+        self.fail(x, y);
+    }
+
+    fn undo_to(&mut self, trail_len: usize) {
+        while self.trail.len() > trail_len {
+            match self.trail.pop().unwrap() {
+                Change::Removed { x, y, spin } => self.options[y][x][spin] = true,
+                Change::Written(position) => {
+                    self.map[position] = 0;
+                    self.collapsed -= 1;
+                }
             }
-            self.options[minimum_entropy.0][minimum_entropy.1][minimum_entropy.2] = true;
-            counts[minimum_entropy.2] += 1;
-            self.branch_out(minimum_entropy.1, minimum_entropy.0);
-            return None;
         }
-        return None;
+        self.map_dirty = true;
     }
 
-    fn calculate_entropy(sum_of_options: &Vec<u64>) -> f64 {
+    fn fail(&mut self, x: usize, y: usize) {
+        log_at!(
+            Warn,
+            Solver,
+            "giving up on a contradiction at ({}, {})",
+            x,
+            y
+        );
+        self.status = Status::Contradiction;
+        self.contradiction = Some((x, y));
+        self.last_conflict = Some((x, y));
+        self.events.push(Event::Contradiction { x, y });
+    }
+
+    fn calculate_entropy(sum_of_options: &[u64]) -> f64 {
         // log!("sum_of_options {:?}", sum_of_options);
 
         let sum: u64 = sum_of_options.iter().sum();
-        let entropy = sum_of_options
+        sum_of_options
             .iter()
             .map(|option| {
                 let p: f64 = *option as f64 / sum as f64;
@@ -208,20 +520,19 @@ impl Context {
                     -p * p.log2()
                 }
             })
-            .sum();
-        entropy
+            .sum()
     }
 
-    fn branch_out(&mut self, x: usize, y: usize) {
-        let mut changed_cells = HashSet::new();
+    // Fails with the first cell that propagation left without any option.
+    fn branch_out(&mut self, x: usize, y: usize) -> Result<(), (usize, usize)> {
+        // Ordered so that runs, and the trail they leave, are reproducible.
+        let mut changed_cells = BTreeSet::new();
         changed_cells.insert((x, y));
-        while changed_cells.len() != 0 {
-            let (x, y) = *changed_cells.iter().next().unwrap();
-            changed_cells.remove(&(x, y));
+        while let Some((x, y)) = changed_cells.pop_first() {
             // log!("changed_cell y {} x {}", y, x);
 
             if x >= self.options[0].len() || y >= self.options.len() {
-                return;
+                return Ok(());
             }
             let offsets: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
 
@@ -237,26 +548,40 @@ impl Context {
                         .iter()
                         .enumerate()
                         .filter_map(|(spin, option)| if *option { Some(spin) } else { None });
-                    let first_hash =
-                        self.borders_hash[(filtered_options.next().unwrap() + 4 - orientation) % 4];
+                    let first_hash = match filtered_options.next() {
+                        Some(spin) => self.borders_hash[(spin + 4 - orientation) % 4],
+                        None => return Err((x, y)),
+                    };
                     for spin in filtered_options {
                         if self.borders_hash[(spin + 4 - orientation) % 4] != first_hash {
                             continue 'big_loop;
                         }
                     }
 
-                    for (spin, option) in self.options[y.wrapping_add(offset.0 as usize)]
-                        [x.wrapping_add(offset.1 as usize)]
-                    .iter_mut()
-                    .enumerate()
-                    .filter(|(_spin, option)| **option)
+                    let neighbor_x = x.wrapping_add(offset.1 as usize);
+                    let neighbor_y = y.wrapping_add(offset.0 as usize);
+                    let neighbor = &mut self.options[neighbor_y][neighbor_x];
+                    for (spin, option) in neighbor
+                        .iter_mut()
+                        .enumerate()
+                        .filter(|(_spin, option)| **option)
                     {
                         if self.borders_hash[(spin + 4 - orientation + 2) % 4] != first_hash {
+                            log_at!(
+                                Trace,
+                                Propagation,
+                                "removing rotation {} at ({}, {})",
+                                spin,
+                                neighbor_x,
+                                neighbor_y
+                            );
                             *option = false;
-                            changed_cells.insert((
-                                x.wrapping_add(offset.1 as usize),
-                                y.wrapping_add(offset.0 as usize),
-                            ));
+                            self.trail.push(Change::Removed {
+                                x: neighbor_x,
+                                y: neighbor_y,
+                                spin,
+                            });
+                            changed_cells.insert((neighbor_x, neighbor_y));
                             // log!(
                             //     "insert y {} x {}",
                             //     y.wrapping_add(offset.0 as usize),
@@ -264,105 +589,19 @@ impl Context {
                             // );
                         }
                     }
+                    if !neighbor.contains(&true) {
+                        log_at!(
+                            Debug,
+                            Propagation,
+                            "no options left at ({}, {})",
+                            neighbor_x,
+                            neighbor_y
+                        );
+                        return Err((neighbor_x, neighbor_y));
+                    }
                 }
             }
         }
-    }
-
-    pub fn render(
-        &mut self,
-        webgl_context: &WebGl2RenderingContext,
-        changed_pixel: Option<usize>,
-        window_width: u32,
-        window_height: u32,
-    ) {
-        if self.texture.is_some() {
-            webgl_context.use_program(self.program.as_ref());
-            webgl_context.uniform1i(self.map_uniform_index.as_ref(), 1);
-            webgl_context.uniform1i(self.texture_uniform_index.as_ref(), 0);
-            webgl_context.uniform2ui(
-                self.window_size_uniform_index.as_ref(),
-                window_width,
-                window_height,
-            );
-            webgl_context.active_texture(WebGl2RenderingContext::TEXTURE0);
-            webgl_context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, self.texture.as_ref());
-            webgl_context.active_texture(WebGl2RenderingContext::TEXTURE1);
-            webgl_context.bind_texture(
-                WebGl2RenderingContext::TEXTURE_2D,
-                self.map_texture.as_ref(),
-            );
-            if let Some(changed_pixel) = changed_pixel {
-                webgl_context
-                    .tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_u8_array(
-                        WebGl2RenderingContext::TEXTURE_2D,
-                        0,
-                        (changed_pixel % self.map_width) as i32,
-                        (changed_pixel / self.map_width) as i32,
-                        1,
-                        1,
-                        WebGl2RenderingContext::RED_INTEGER,
-                        WebGl2RenderingContext::UNSIGNED_BYTE,
-                        Some(&self.map[changed_pixel as usize..]),
-                    )
-                    .unwrap();
-            }
-            webgl_context.draw_arrays(WebGl2RenderingContext::TRIANGLE_STRIP, 0, 4);
-        }
-    }
-
-    fn get_border_of_image(&self, border: i32) -> u64 {
-        let image = self.image.as_ref().unwrap().clone();
-        match border {
-            0 => {
-                let mut hash = DefaultHasher::new();
-                hash.write(
-                    &image
-                        .data()
-                        .chunks(image.width() as usize * 4)
-                        .last()
-                        .unwrap(),
-                );
-
-                hash.finish()
-            }
-            1 => {
-                let mut hash = DefaultHasher::new();
-                image
-                    .data()
-                    .0
-                    .chunks(4)
-                    .into_iter()
-                    .step_by(image.width() as usize)
-                    .for_each(|v| hash.write(v));
-
-                hash.finish()
-            }
-            2 => {
-                let mut hash = DefaultHasher::new();
-                hash.write(
-                    &image
-                        .data()
-                        .chunks(image.width() as usize * 4)
-                        .next()
-                        .unwrap(),
-                );
-                hash.finish()
-            }
-            3 => {
-                let mut hash = DefaultHasher::new();
-                image
-                    .data()
-                    .0
-                    .chunks(4)
-                    .into_iter()
-                    .skip(image.width() as usize - 1)
-                    .step_by(image.width() as usize)
-                    .for_each(|v| hash.write(v));
-
-                hash.finish()
-            }
-            _ => 0,
-        }
+        Ok(())
     }
 }
@@ -18,3 +18,34 @@ macro_rules! log {
         web_sys::console::log_1(&format!( $( $t )* ).into());
     }
 }
+
+// Logs under one of the `logging` categories, e.g.
+// `log_at!(Debug, Solver, "collapsed {} {}", x, y)`. The message is only
+// formatted when that category is set to log at this level, and without the
+// `logging` feature the whole thing compiles to nothing.
+#[cfg(feature = "logging")]
+#[macro_export]
+macro_rules! log_at {
+    ( $level:ident, $category:ident, $( $t:tt )* ) => {
+        if $crate::logging::enabled(
+            $crate::logging::Category::$category,
+            $crate::logging::Level::$level,
+        ) {
+            $crate::logging::write(
+                $crate::logging::Category::$category,
+                $crate::logging::Level::$level,
+                &format!( $( $t )* ),
+            );
+        }
+    }
+}
+
+#[cfg(not(feature = "logging"))]
+#[macro_export]
+macro_rules! log_at {
+    ( $level:ident, $category:ident, $( $t:tt )* ) => {
+        if false {
+            let _ = format_args!( $( $t )* );
+        }
+    }
+}
@@ -0,0 +1,94 @@
+// Which part of the map is on screen. The renderers fit the whole map into
+// the largest centered square of the canvas; `zoom` scales that up, and
+// `offset` is the map position, from 0 to 1 across it with y going down the
+// rows, that ends up at the top left corner of the square.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct View {
+    pub zoom: f64,
+    pub offset: (f64, f64),
+}
+
+pub const MAX_ZOOM: f64 = 64.;
+
+// Zoomed in, the atlas is sampled at the nearest texel once one covers this
+// many pixels, so that tiles stay crisp instead of blurring.
+const CRISP_TEXEL_PIXELS: f64 = 2.;
+
+impl Default for View {
+    fn default() -> Self {
+        View {
+            zoom: 1.,
+            offset: (0., 0.),
+        }
+    }
+}
+
+impl View {
+    // Where a point of the square lands on the map, both from 0 to 1.
+    pub fn to_map(&self, point: (f64, f64)) -> (f64, f64) {
+        (
+            point.0 / self.zoom + self.offset.0,
+            point.1 / self.zoom + self.offset.1,
+        )
+    }
+
+    // The `(x, y)` of the map cell under a point of the square, if any.
+    pub fn cell_at(
+        &self,
+        point: (f64, f64),
+        map_width: usize,
+        map_height: usize,
+    ) -> Option<(usize, usize)> {
+        let (x, y) = self.to_map(point);
+        if !(0. ..1.).contains(&x) || !(0. ..1.).contains(&y) {
+            return None;
+        }
+        Some((
+            ((x * map_width as f64) as usize).min(map_width - 1),
+            ((y * map_height as f64) as usize).min(map_height - 1),
+        ))
+    }
+
+    // Zooms by `factor` keeping the map under `point` in place.
+    pub fn zoom_at(&mut self, factor: f64, point: (f64, f64)) {
+        let anchor = self.to_map(point);
+        self.zoom = (self.zoom * factor).clamp(1., MAX_ZOOM);
+        self.offset = (
+            anchor.0 - point.0 / self.zoom,
+            anchor.1 - point.1 / self.zoom,
+        );
+        self.clamp();
+    }
+
+    // Drags the map by `delta` in square units.
+    pub fn pan(&mut self, delta: (f64, f64)) {
+        self.offset.0 -= delta.0 / self.zoom;
+        self.offset.1 -= delta.1 / self.zoom;
+        self.clamp();
+    }
+
+    // Keeps the square filled with map.
+    fn clamp(&mut self) {
+        let max = 1. - 1. / self.zoom;
+        self.offset = (self.offset.0.clamp(0., max), self.offset.1.clamp(0., max));
+    }
+
+    // Whether a tile `tile_width` texels wide, on a map `map_width` cells
+    // wide fitted into `min_size` pixels, is zoomed in far enough to be
+    // sampled at the nearest texel.
+    pub fn crisp(&self, min_size: f64, map_width: usize, tile_width: usize) -> bool {
+        let texel_pixels = min_size * self.zoom / (map_width * tile_width) as f64;
+        self.zoom > 1. && texel_pixels >= CRISP_TEXEL_PIXELS
+    }
+}
+
+// Canvas pixel coordinates, rows from the top, in square units as `View`
+// takes them.
+pub fn to_square(window_size: (u32, u32), pixel: (f64, f64)) -> (f64, f64) {
+    let (width, height) = (window_size.0 as f64, window_size.1 as f64);
+    let min_size = width.min(height);
+    (
+        (pixel.0 - (width - min_size) / 2.) / min_size,
+        (pixel.1 - (height - min_size) / 2.) / min_size,
+    )
+}
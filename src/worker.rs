@@ -0,0 +1,380 @@
+use crate::config::get_number;
+use crate::error::Error;
+use crate::events::Event;
+use crate::generate::set_timeout;
+use crate::tileset::Tileset;
+use crate::update::{Context, Status};
+use crate::utils::set_panic_hook;
+use js_sys::{Array, ArrayBuffer, Date, Object, Reflect, Uint32Array, Uint8Array};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{DedicatedWorkerGlobalScope, MessageEvent, Worker};
+
+// The solver can run in a dedicated worker, talking to the page with plain
+// messages tagged by `type`:
+//
+// page -> worker: `start` (tileset pixels, config and pins), `pause`,
+//                 `resume`, `step`, `restart` (optional `seed`), `display`
+//                 (`masks`, whether to send them), `pin` (`x`, `y` and an
+//                 optional `rotation`), `clear_pins`
+// worker -> page: `ready`, `cells` (flat `[position, value, ...]` pairs),
+//                 `map` (the whole map, after backtracking), `masks` (the
+//                 options left in every cell, when asked for), `event`
+
+// Milliseconds of solving between two batches of messages.
+const TIME_SLICE: f64 = 8.;
+
+#[derive(Default)]
+struct WorkerState {
+    context: Option<Context>,
+    paused: bool,
+    scheduled: bool,
+    send_masks: bool,
+}
+
+type TickCallback = Rc<RefCell<Option<Closure<dyn FnMut()>>>>;
+
+// Entry point for the worker script, to be called once the module has been
+// initialised there.
+#[wasm_bindgen]
+pub fn worker_main() -> Result<(), JsValue> {
+    set_panic_hook();
+    let scope = js_sys::global().dyn_into::<DedicatedWorkerGlobalScope>()?;
+    let state = Rc::new(RefCell::new(WorkerState::default()));
+    let tick: TickCallback = Rc::new(RefCell::new(None));
+    {
+        let scope = scope.clone();
+        let state = state.clone();
+        let next = tick.clone();
+        *tick.borrow_mut() = Some(Closure::<dyn FnMut()>::new(move || {
+            let mut state = state.borrow_mut();
+            state.scheduled = false;
+            let result = state.run_slice(&scope);
+            if let Err(error) = result {
+                web_sys::console::error_1(&error);
+                return;
+            }
+            state.schedule(&next);
+        }));
+    }
+    let closure = {
+        let scope = scope.clone();
+        Closure::<dyn FnMut(MessageEvent)>::new(move |message: MessageEvent| {
+            let mut state = state.borrow_mut();
+            if let Err(error) = state.receive(&scope, &message.data()) {
+                web_sys::console::error_1(&error);
+            }
+            state.schedule(&tick);
+        })
+    };
+    scope.set_onmessage(Some(closure.as_ref().unchecked_ref()));
+    // Lives as long as the worker does.
+    closure.forget();
+    scope.post_message(&message("ready")?.into())
+}
+
+impl WorkerState {
+    fn receive(
+        &mut self,
+        scope: &DedicatedWorkerGlobalScope,
+        data: &JsValue,
+    ) -> Result<(), JsValue> {
+        let kind = Reflect::get(data, &JsValue::from_str("type"))?.as_string();
+        match kind.as_deref() {
+            Some("start") => {
+                let width = get_number(data, "width")?.unwrap_or(0.) as usize;
+                let height = get_number(data, "height")?.unwrap_or(0.) as usize;
+                let pixels = Reflect::get(data, &JsValue::from_str("tileset"))?;
+                let tileset_width = get_number(data, "tileset_width")?.unwrap_or(0.) as usize;
+                let tileset_height = get_number(data, "tileset_height")?.unwrap_or(0.) as usize;
+                let mut context = Context::new(width, height);
+                let pins = Reflect::get(data, &JsValue::from_str("pins"))?;
+                if !pins.is_undefined() {
+                    Uint8Array::new(&pins).copy_to(&mut context.pins[..]);
+                }
+                context.set_tileset(Tileset::new(
+                    Uint8Array::new(&pixels).to_vec(),
+                    tileset_width,
+                    tileset_height,
+                )?);
+                if let Some(max_backtracks) = get_number(data, "max_backtracks")? {
+                    context.max_backtracks = max_backtracks as usize;
+                }
+                context.set_seed(get_number(data, "seed")?.unwrap_or(0.) as u64);
+                self.context = Some(context);
+                self.paused = false;
+                self.send_masks = get_bool(data, "masks")?;
+            }
+            Some("pause") => self.paused = true,
+            Some("resume") => self.paused = false,
+            Some("step") => {
+                if let Some(context) = self.context.as_mut() {
+                    let changed = context.step();
+                    flush(scope, context, changed.into_iter().collect())?;
+                    if self.send_masks {
+                        post_masks(scope, context)?;
+                    }
+                }
+            }
+            Some("restart") => {
+                if let Some(context) = self.context.as_mut() {
+                    match get_number(data, "seed")? {
+                        Some(seed) => context.set_seed(seed as u64),
+                        None => context.reset(),
+                    }
+                    flush(scope, context, vec![])?;
+                    if self.send_masks {
+                        post_masks(scope, context)?;
+                    }
+                }
+            }
+            Some("pin") | Some("clear_pins") => {
+                if let Some(context) = self.context.as_mut() {
+                    if kind.as_deref() == Some("pin") {
+                        let x = get_number(data, "x")?.unwrap_or(0.) as usize;
+                        let y = get_number(data, "y")?.unwrap_or(0.) as usize;
+                        let rotation = get_number(data, "rotation")?.map(|r| r as usize);
+                        context.check_pin(x, y, rotation)?;
+                        context.pin(x, y, rotation);
+                    } else {
+                        context.clear_pins();
+                    }
+                    flush(scope, context, vec![])?;
+                    if self.send_masks {
+                        post_masks(scope, context)?;
+                    }
+                }
+            }
+            Some("display") => {
+                self.send_masks = get_bool(data, "masks")?;
+                if let (true, Some(context)) = (self.send_masks, self.context.as_ref()) {
+                    post_masks(scope, context)?;
+                }
+            }
+            _ => return Err(Error::invalid("unknown message").into()),
+        }
+        Ok(())
+    }
+
+    fn run_slice(&mut self, scope: &DedicatedWorkerGlobalScope) -> Result<(), JsValue> {
+        let context = match self.context.as_mut() {
+            Some(context) => context,
+            None => return Ok(()),
+        };
+        let deadline = Date::now() + TIME_SLICE;
+        let mut changed = vec![];
+        let mut stepped = false;
+        while context.status == Status::Running && Date::now() < deadline {
+            changed.extend(context.step());
+            stepped = true;
+        }
+        flush(scope, context, changed)?;
+        if stepped && self.send_masks {
+            post_masks(scope, context)?;
+        }
+        Ok(())
+    }
+
+    fn schedule(&mut self, tick: &TickCallback) {
+        let running = self
+            .context
+            .as_ref()
+            .is_some_and(|context| context.status == Status::Running);
+        if running && !self.paused && !self.scheduled {
+            self.scheduled = true;
+            set_timeout(tick.borrow().as_ref().unwrap().as_ref().unchecked_ref(), 0);
+        }
+    }
+}
+
+// Sends what changed since the last flush, and then the queued events.
+fn flush(
+    scope: &DedicatedWorkerGlobalScope,
+    context: &mut Context,
+    changed: Vec<usize>,
+) -> Result<(), JsValue> {
+    if let Some((data, buffer)) = map_message(context, changed)? {
+        scope.post_message_with_transfer(&data, &Array::of1(&buffer))?;
+    }
+    let events = std::mem::take(&mut context.events);
+    // As with the handlers, only the latest progress is worth sending.
+    let last_progress = events
+        .iter()
+        .rposition(|event| matches!(event, Event::Progress { .. }));
+    for (i, event) in events.into_iter().enumerate() {
+        if matches!(event, Event::Progress { .. }) && Some(i) != last_progress {
+            continue;
+        }
+        let data = event.to_js(context)?;
+        Reflect::set(
+            &data,
+            &JsValue::from_str("type"),
+            &JsValue::from_str("event"),
+        )?;
+        Reflect::set(
+            &data,
+            &JsValue::from_str("event"),
+            &JsValue::from_str(event.name()),
+        )?;
+        scope.post_message(&data)?;
+    }
+    Ok(())
+}
+
+// The written cells, or the whole map when backtracking touched more than
+// those, together with the buffer to transfer. None when nothing changed.
+pub fn map_message(
+    context: &mut Context,
+    changed: Vec<usize>,
+) -> Result<Option<(Object, ArrayBuffer)>, JsValue> {
+    if context.map_dirty {
+        context.map_dirty = false;
+        let map = Uint8Array::from(&context.map[..]);
+        let data = message("map")?;
+        Reflect::set(&data, &JsValue::from_str("map"), &map)?;
+        Ok(Some((data, map.buffer())))
+    } else if !changed.is_empty() {
+        let cells = changed
+            .into_iter()
+            .flat_map(|position| [position as u32, context.map[position] as u32])
+            .collect::<Vec<_>>();
+        let cells = Uint32Array::from(&cells[..]);
+        let data = message("cells")?;
+        Reflect::set(&data, &JsValue::from_str("cells"), &cells)?;
+        Ok(Some((data, cells.buffer())))
+    } else {
+        Ok(None)
+    }
+}
+
+fn post_masks(scope: &DedicatedWorkerGlobalScope, context: &Context) -> Result<(), JsValue> {
+    let masks = Uint8Array::from(&context.option_masks()[..]);
+    let data = message("masks")?;
+    Reflect::set(&data, &JsValue::from_str("masks"), &masks)?;
+    scope.post_message_with_transfer(&data, &Array::of1(&masks.buffer()))
+}
+
+fn get_bool(data: &JsValue, key: &str) -> Result<bool, JsValue> {
+    Ok(Reflect::get(data, &JsValue::from_str(key))?.is_truthy())
+}
+
+fn message(kind: &str) -> Result<Object, JsValue> {
+    let data = Object::new();
+    Reflect::set(&data, &JsValue::from_str("type"), &JsValue::from_str(kind))?;
+    Ok(data)
+}
+
+// Page side of the protocol.
+
+pub fn post_start(worker: &Worker, context: &Context, masks: bool) -> Result<(), JsValue> {
+    let tileset = context.tileset.as_ref().unwrap();
+    let data = message("start")?;
+    let number =
+        |key: &str, value: f64| Reflect::set(&data, &JsValue::from_str(key), &value.into());
+    number("width", context.map_width as f64)?;
+    number("height", context.map_height as f64)?;
+    number("seed", context.seed as f64)?;
+    number("max_backtracks", context.max_backtracks as f64)?;
+    number("tileset_width", tileset.width as f64)?;
+    number("tileset_height", tileset.height as f64)?;
+    Reflect::set(
+        &data,
+        &JsValue::from_str("tileset"),
+        &Uint8Array::from(&tileset.data[..]),
+    )?;
+    Reflect::set(&data, &JsValue::from_str("masks"), &masks.into())?;
+    Reflect::set(
+        &data,
+        &JsValue::from_str("pins"),
+        &Uint8Array::from(&context.pins[..]),
+    )?;
+    worker.post_message(&data)
+}
+
+pub fn post_display(worker: &Worker, masks: bool) -> Result<(), JsValue> {
+    let data = message("display")?;
+    Reflect::set(&data, &JsValue::from_str("masks"), &masks.into())?;
+    worker.post_message(&data)
+}
+
+pub fn post_command(worker: &Worker, kind: &str, seed: Option<u32>) -> Result<(), JsValue> {
+    let data = message(kind)?;
+    if let Some(seed) = seed {
+        Reflect::set(&data, &JsValue::from_str("seed"), &seed.into())?;
+    }
+    worker.post_message(&data)
+}
+
+// `rotation` None unpins the cell.
+pub fn post_pin(
+    worker: &Worker,
+    x: usize,
+    y: usize,
+    rotation: Option<usize>,
+) -> Result<(), JsValue> {
+    let data = message("pin")?;
+    Reflect::set(&data, &JsValue::from_str("x"), &x.into())?;
+    Reflect::set(&data, &JsValue::from_str("y"), &y.into())?;
+    if let Some(rotation) = rotation {
+        Reflect::set(&data, &JsValue::from_str("rotation"), &rotation.into())?;
+    }
+    worker.post_message(&data)
+}
+
+// Applies a message from the worker to the page's copy of the map, adding the
+// cells it wrote to `changed`. Returns true for the `ready` message.
+pub fn receive(
+    context: &mut Context,
+    data: &JsValue,
+    changed: &mut Vec<usize>,
+) -> Result<bool, JsValue> {
+    let kind = Reflect::get(data, &JsValue::from_str("type"))?.as_string();
+    match kind.as_deref() {
+        Some("ready") => return Ok(true),
+        Some("cells") => {
+            let cells = Uint32Array::new(&Reflect::get(data, &JsValue::from_str("cells"))?);
+            for cell in cells.to_vec().chunks_exact(2) {
+                let position = cell[0] as usize;
+                if position >= context.map.len() {
+                    continue;
+                }
+                context.map[position] = cell[1] as u8;
+                if cell[1] != 0 {
+                    context.last_collapsed = Some(position);
+                }
+                changed.push(position);
+            }
+        }
+        Some("map") => {
+            let map = Uint8Array::new(&Reflect::get(data, &JsValue::from_str("map"))?);
+            if map.length() as usize != context.map.len() {
+                return Err(Error::invalid("map of the wrong size").into());
+            }
+            map.copy_to(&mut context.map[..]);
+            context.last_collapsed = None;
+            context.map_dirty = true;
+        }
+        Some("masks") => {
+            let masks = Uint8Array::new(&Reflect::get(data, &JsValue::from_str("masks"))?);
+            context.set_option_masks(&masks.to_vec());
+        }
+        Some("event") => {
+            let event = Event::from_js(data)?;
+            match event {
+                Event::Complete => context.status = Status::Done,
+                Event::Contradiction { x, y } => {
+                    context.status = Status::Contradiction;
+                    context.contradiction = Some((x, y));
+                    context.last_conflict = Some((x, y));
+                }
+                Event::Backtrack { x, y, .. } => context.last_conflict = Some((x, y)),
+                _ => {}
+            }
+            context.events.push(event);
+        }
+        _ => return Err(Error::invalid("unknown message").into()),
+    }
+    Ok(false)
+}
@@ -0,0 +1,35 @@
+//! Fixtures shared by the tests that run without a browser.
+
+// Each test crate uses some of these.
+#![allow(dead_code)]
+
+use wasm_wfc::{Context, Tileset};
+
+// A 3x3 red ramp. Its borders all differ, so a single decision settles the
+// whole map.
+pub fn ramp_tileset() -> Tileset {
+    let mut data = vec![0u8; 3 * 3 * 4];
+    for (i, pixel) in data.chunks_mut(4).enumerate() {
+        pixel[0] = i as u8 * 20;
+        pixel[3] = 255;
+    }
+    Tileset::new(data, 3, 3).unwrap()
+}
+
+// A 2x2 tile with a single lit corner, loose enough that a map takes several
+// decisions.
+pub fn corner_tileset() -> Tileset {
+    let mut data = vec![0u8; 2 * 2 * 4];
+    data[0] = 200;
+    for pixel in data.chunks_mut(4) {
+        pixel[3] = 255;
+    }
+    Tileset::new(data, 2, 2).unwrap()
+}
+
+pub fn context(tileset: Tileset, width: usize, height: usize, seed: u64) -> Context {
+    let mut context = Context::new(width, height);
+    context.set_tileset(tileset);
+    context.set_seed(seed);
+    context
+}
@@ -0,0 +1,37 @@
+//! Map exporters, which run without a browser.
+
+mod common;
+
+use common::corner_tileset;
+use wasm_wfc::{Context, Error};
+
+#[test]
+fn json_and_csv_carry_the_map_and_its_config() {
+    let mut context = Context::new(3, 2);
+    context.set_seed(7);
+    context.map = vec![0, 1, 2, 3, 4, 1];
+    let json = context.to_json();
+    assert!(json.starts_with(
+        "{\"seed\":7,\"config\":{\"width\":3,\"height\":2,\"seed\":7,\"max_backtracks\":1000}"
+    ));
+    assert!(json.contains("{\"id\":2,\"name\":\"tile\",\"rotation\":90}"));
+    assert!(json.ends_with("\"map\":[[0,1,2],[3,4,1]]}"));
+    let csv = context.to_csv();
+    let rows = csv.lines().filter(|line| !line.starts_with('#'));
+    assert_eq!(rows.collect::<Vec<_>>(), ["0,1,2", "3,4,1"]);
+    assert!(csv.starts_with("# seed=7 width=3 height=2 max_backtracks=1000\n"));
+}
+
+#[test]
+fn png_scales_past_the_size_limit_are_rejected() {
+    let context = common::context(corner_tileset(), 3, 2, 0);
+    let png = context.to_png(Some(4)).unwrap();
+    // Width and height of the IHDR chunk.
+    assert_eq!(&png[16..24], &[0, 0, 0, 12, 0, 0, 0, 8]);
+    for scale in [100_000, usize::MAX] {
+        assert!(matches!(
+            context.to_png(Some(scale)),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+}
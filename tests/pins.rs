@@ -0,0 +1,69 @@
+//! Pinning cells while the solver runs, without a browser.
+
+mod common;
+
+use common::corner_tileset;
+use wasm_wfc::{Change, Context, Status};
+
+// Six decisions fill this map.
+fn context() -> Context {
+    common::context(corner_tileset(), 6, 2, 0)
+}
+
+fn run(context: &mut Context) {
+    while context.status == Status::Running {
+        context.step();
+    }
+}
+
+fn decisions(context: &Context) -> Vec<(usize, usize, usize)> {
+    context
+        .decisions
+        .iter()
+        .map(|decision| (decision.x, decision.y, decision.spin))
+        .collect()
+}
+
+#[test]
+fn a_conflicting_pin_keeps_the_decisions_before_the_conflict() {
+    let mut context = context();
+    run(&mut context);
+    assert_eq!(context.status, Status::Done);
+    let before = decisions(&context);
+    assert_eq!(before.len(), 6);
+
+    // The fifth decision no longer fits next to this pin.
+    context.pin(3, 1, Some(0));
+    assert_eq!(decisions(&context), before[..4]);
+    assert_eq!(context.status, Status::Running);
+    // The pinned cell is written ahead of the decisions, so undoing them
+    // leaves it in the map.
+    let written = context
+        .trail
+        .iter()
+        .position(|change| *change == Change::Written(6 + 3))
+        .unwrap();
+    assert!(written < context.decisions[0].trail_len);
+
+    run(&mut context);
+    assert_eq!(context.status, Status::Done);
+    assert_eq!(context.map[6 + 3], 1);
+    assert_eq!(decisions(&context)[..4], before[..4]);
+}
+
+#[test]
+fn pins_survive_a_snapshot() {
+    let mut pinned = context();
+    pinned.pin(3, 1, Some(0));
+    for _ in 0..3 {
+        pinned.step();
+    }
+    let mut restored = context();
+    restored.restore(&pinned.snapshot()).unwrap();
+    assert_eq!(restored.pins, pinned.pins);
+
+    // A rebuild after the restore still starts from the restored pin.
+    restored.pin(0, 0, None);
+    run(&mut restored);
+    assert_eq!(restored.map[6 + 3], 1);
+}
@@ -0,0 +1,207 @@
+//! The CPU rasterizer, which runs without a browser.
+
+use wasm_wfc::{
+    composite, rasterize, Context, DisplayMode, HeadlessRenderer, Highlight, Highlights, Renderer,
+    Tileset, Transition, TransitionStyle, View,
+};
+
+fn tileset() -> Tileset {
+    let data = (0..4 * 4 * 4).map(|i| (i * 3) as u8).collect();
+    Tileset::new(data, 4, 4).unwrap()
+}
+
+fn context(width: usize, height: usize) -> Context {
+    let mut context = Context::new(width, height);
+    context.set_tileset(tileset());
+    context
+}
+
+// A renderer set up by `configure` that has drawn all of `context`.
+fn render(
+    context: &Context,
+    width: u32,
+    height: u32,
+    configure: impl FnOnce(&mut HeadlessRenderer),
+) -> HeadlessRenderer {
+    let mut renderer = HeadlessRenderer::new();
+    renderer
+        .set_tileset(context.tileset.as_ref().unwrap())
+        .unwrap();
+    configure(&mut renderer);
+    renderer.resize(width, height);
+    renderer.update_cells(context, None).unwrap();
+    renderer.draw().unwrap();
+    renderer
+}
+
+#[test]
+fn composite_matches_tile_rotations() {
+    let tileset = tileset();
+    let pixels = composite(&[1, 2, 3, 0], 2, 2, &tileset, 4);
+    let texel = |x: usize, y: usize| &tileset.data[(y * 4 + x) * 4..(y * 4 + x) * 4 + 4];
+    let pixel = |x: usize, y: usize| &pixels[(y * 8 + x) * 4..(y * 8 + x) * 4 + 4];
+    for j in 0..4 {
+        for i in 0..4 {
+            assert_eq!(pixel(i, j), texel(i, j));
+            assert_eq!(pixel(4 + i, j), texel(3 - j, i));
+            assert_eq!(pixel(i, 4 + j), texel(3 - i, 3 - j));
+            assert_eq!(pixel(4 + i, 4 + j), &[230, 230, 230, 255]);
+        }
+    }
+}
+
+#[test]
+fn rasterize_letterboxes_like_the_shader() {
+    let tileset = tileset();
+    let map = [1, 4, 2, 0];
+    let pixels = rasterize(&map, 2, 2, &tileset, 12, 8);
+    // The map fills the centered 8x8 square, the sides stay transparent.
+    assert!(pixels[..2 * 4].iter().all(|channel| *channel == 0));
+    assert!(pixels[10 * 4..12 * 4].iter().all(|channel| *channel == 0));
+    let centered = composite(&map, 2, 2, &tileset, 4);
+    for row in 0..8 {
+        assert_eq!(
+            &pixels[(row * 12 + 2) * 4..(row * 12 + 10) * 4],
+            &centered[row * 8 * 4..(row + 1) * 8 * 4]
+        );
+    }
+}
+
+#[test]
+fn headless_renderer_follows_cell_updates() {
+    let mut context = context(3, 2);
+    let mut renderer = render(&context, 12, 8, |_| {});
+    context.map[1] = 2;
+    context.map[5] = 4;
+    renderer.update_cells(&context, Some(&[1, 5])).unwrap();
+    renderer.draw().unwrap();
+    assert_eq!(
+        renderer.pixels,
+        rasterize(&context.map, 3, 2, &tileset(), 12, 8)
+    );
+}
+
+#[test]
+fn domain_size_mode_shades_undecided_cells() {
+    let mut context = context(2, 2);
+    context.map[0] = 1;
+    let renderer = render(&context, 4, 4, |renderer| {
+        renderer.set_display_mode(DisplayMode::DomainSize)
+    });
+    let pixel = |x: usize, y: usize| &renderer.pixels[(y * 4 + x) * 4..(y * 4 + x) * 4 + 4];
+    // All four rotations are still open everywhere but in the decided cell.
+    assert_eq!(
+        pixel(1, 1),
+        &rasterize(&context.map, 2, 2, &tileset(), 4, 4)[20..24]
+    );
+    assert_eq!(pixel(2, 0), &[64, 89, 204, 255]);
+    assert_eq!(pixel(3, 3), &[64, 89, 204, 255]);
+}
+
+#[test]
+fn entropy_overlay_tints_undecided_cells() {
+    let mut context = context(2, 1);
+    context.options[0][1] = vec![true, false, false, false];
+    let renderer = render(&context, 4, 4, |renderer| {
+        renderer.set_entropy_overlay(true)
+    });
+    // Blue over the grey of a cell with all four options, red with one.
+    assert_eq!(&renderer.pixels[..4], &[132, 167, 237, 255]);
+    assert_eq!(&renderer.pixels[12..16], &[230, 132, 118, 255]);
+}
+
+#[test]
+fn zoomed_view_shows_part_of_the_map() {
+    let mut context = context(2, 2);
+    context.map = vec![1, 2, 3, 4];
+    let mut view = View::default();
+    // Zooming in on the top right corner, which stays where it was.
+    view.zoom_at(2., (1., 0.));
+    assert_eq!(view.offset, (0.5, 0.));
+    view.pan((1., 0.));
+    assert_eq!(view.offset, (0., 0.));
+    view.pan((-1., 0.));
+    let renderer = render(&context, 4, 4, |renderer| renderer.set_view(view));
+    assert_eq!(renderer.pixels, composite(&[2], 1, 1, &tileset(), 4));
+}
+
+#[test]
+fn pixel_art_samples_the_nearest_texel() {
+    let mut context = context(1, 1);
+    context.map[0] = 1;
+    let renderer = render(&context, 8, 8, |renderer| renderer.set_pixel_art(true));
+    let tileset = tileset();
+    for row in 0..8 {
+        for column in 0..8 {
+            let texel = ((row / 2) * 4 + column / 2) * 4;
+            let pixel = (row * 8 + column) * 4;
+            assert_eq!(
+                renderer.pixels[pixel..pixel + 4],
+                tileset.data[texel..texel + 4]
+            );
+        }
+    }
+}
+
+#[test]
+fn highlights_outline_cells_and_draw_the_grid() {
+    let mut highlights = Highlights {
+        grid: true,
+        ..Highlights::default()
+    };
+    highlights.set(Highlight::Selected, Some(0));
+    let renderer = render(&context(2, 2), 16, 16, |renderer| {
+        renderer.set_highlights(&highlights)
+    });
+    let pixel = |x: usize, y: usize| &renderer.pixels[(y * 16 + x) * 4..(y * 16 + x) * 4 + 4];
+    let selected = highlights.colors[Highlight::Selected as usize];
+    // Two pixels of outline inside the selected cell, the grey within.
+    assert_eq!(pixel(1, 4), &selected);
+    assert_eq!(pixel(6, 4), &selected);
+    assert_eq!(pixel(4, 4), &[230, 230, 230, 255]);
+    // The grid along the right and bottom of the others.
+    assert_eq!(pixel(15, 12), &[172, 172, 172, 255]);
+    assert_eq!(pixel(12, 15), &[172, 172, 172, 255]);
+    assert_eq!(pixel(12, 12), &[230, 230, 230, 255]);
+}
+
+#[test]
+fn collapsed_cells_fade_in_over_the_transition() {
+    let mut context = context(1, 1);
+    let mut renderer = render(&context, 4, 4, |renderer| {
+        renderer.set_transition(Transition {
+            duration: 100.,
+            style: TransitionStyle::Fade,
+        })
+    });
+    context.map[0] = 1;
+    renderer.set_clock(50.);
+    renderer.update_cells(&context, Some(&[0])).unwrap();
+    let shown = rasterize(&context.map, 1, 1, &tileset(), 4, 4);
+
+    renderer.set_clock(100.);
+    renderer.draw().unwrap();
+    // Halfway, and halfway eased too, from the undecided grey.
+    let grey = [230., 230., 230., 255.];
+    let halfway = |channel: usize| ((grey[channel] + shown[channel] as f64) / 2.).round() as u8;
+    assert_eq!(
+        renderer.pixels[..4],
+        [halfway(0), halfway(1), halfway(2), halfway(3)]
+    );
+
+    renderer.set_clock(150.);
+    renderer.draw().unwrap();
+    assert_eq!(renderer.pixels, shown);
+}
+
+#[test]
+fn transition_durations_below_zero_are_clamped_and_non_finite_ones_refused() {
+    let mut transition = Transition::default();
+    transition.set_duration(-20.).unwrap();
+    assert_eq!(transition.duration, 0.);
+    transition.set_duration(80.).unwrap();
+    for duration in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+        assert!(transition.set_duration(duration).is_err());
+    }
+    assert_eq!(transition.duration, 80.);
+}
@@ -0,0 +1,69 @@
+//! Solver snapshots, which run without a browser.
+
+mod common;
+
+use common::ramp_tileset;
+use wasm_wfc::{Context, Status};
+
+fn context() -> Context {
+    common::context(ramp_tileset(), 6, 4, 5)
+}
+
+#[test]
+fn corrupted_snapshots_are_rejected_rather_than_panicking() {
+    let mut solved = context();
+    for _ in 0..10 {
+        solved.step();
+    }
+    let snapshot = solved.snapshot();
+    let mut restored = context();
+    restored.restore(&snapshot).unwrap();
+    assert_eq!(restored.snapshot(), snapshot);
+
+    // Width of 0, and a width times height that overflows.
+    let mut bad = snapshot.clone();
+    bad[4..8].copy_from_slice(&0u32.to_le_bytes());
+    assert!(context().restore(&bad).is_err());
+    bad[4..12].copy_from_slice(&[255; 8]);
+    assert!(context().restore(&bad).is_err());
+
+    // Whatever a corrupted byte does, restoring either fails or leaves a
+    // solver that keeps working.
+    for index in 4..snapshot.len() {
+        for value in [0, 4, 255] {
+            let mut bad = snapshot.clone();
+            bad[index] = value;
+            let mut restored = context();
+            if restored.restore(&bad).is_ok() {
+                for _ in 0..50 {
+                    restored.step();
+                }
+                restored.pin(0, 0, Some(1));
+                while restored.status == Status::Running {
+                    restored.step();
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn restoring_drops_the_log_and_replay_of_the_replaced_run() {
+    let mut context = context();
+    let snapshot = context.snapshot();
+    while context.status == Status::Running {
+        context.step();
+    }
+    let log = context.decision_log.clone();
+    assert!(!log.is_empty());
+
+    context.start_replay(log.iter().rev().cloned().collect());
+    context.restore(&snapshot).unwrap();
+    assert!(context.decision_log.is_empty());
+    assert!(context.replay.is_empty());
+    // Carrying on makes the same run again, logged once.
+    while context.status == Status::Running {
+        context.step();
+    }
+    assert_eq!(context.decision_log, log);
+}
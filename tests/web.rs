@@ -3,11 +3,304 @@
 #![cfg(target_arch = "wasm32")]
 
 extern crate wasm_bindgen_test;
+use js_sys::{Function, Object, Reflect, Uint32Array, Uint8Array};
+use std::cell::Cell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::{Clamped, JsCast};
+use wasm_bindgen_futures::JsFuture;
 use wasm_bindgen_test::*;
+use wasm_wfc::{
+    generate, log_enabled, map_message, receive, set_log_level, Context, Status, Tileset,
+    WfcGenerator,
+};
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, ImageData};
 
 wasm_bindgen_test_configure!(run_in_browser);
 
+fn tileset() -> ImageData {
+    let mut data = vec![0u8; 3 * 3 * 4];
+    for (i, pixel) in data.chunks_mut(4).enumerate() {
+        pixel[0] = i as u8 * 20;
+        pixel[3] = 255;
+    }
+    ImageData::new_with_u8_clamped_array(Clamped(&data), 3).unwrap()
+}
+
+fn config(seed: u32) -> JsValue {
+    js_sys::JSON::parse(&format!(
+        r#"{{"width": 12, "height": 8, "seed": {}}}"#,
+        seed
+    ))
+    .unwrap()
+}
+
 #[wasm_bindgen_test]
 fn pass() {
     assert_eq!(1 + 1, 2);
 }
+
+#[wasm_bindgen_test]
+fn generator_is_deterministic_for_a_seed() {
+    let mut first = WfcGenerator::new(&tileset(), config(7)).unwrap();
+    let mut second = WfcGenerator::new(&tileset(), config(7)).unwrap();
+    assert_eq!(first.run(None).unwrap(), Status::Done);
+    assert_eq!(second.run(None).unwrap(), Status::Done);
+    assert_eq!(first.get_map(), second.get_map());
+    assert!(first.get_map().iter().all(|cell| *cell != 0));
+
+    first.reset();
+    assert_eq!(first.status(), Status::Running);
+    assert!(first.get_map().iter().all(|cell| *cell == 0));
+}
+
+#[wasm_bindgen_test]
+fn generator_reports_progress_and_completion() {
+    let progress = Rc::new(Cell::new(0.));
+    let completed = Rc::new(Cell::new(false));
+    let onprogress = {
+        let progress = progress.clone();
+        Closure::<dyn FnMut(JsValue)>::new(move |event: JsValue| {
+            let collapsed = Reflect::get(&event, &"collapsed".into()).unwrap();
+            progress.set(collapsed.as_f64().unwrap());
+        })
+    };
+    let oncomplete = {
+        let completed = completed.clone();
+        Closure::<dyn FnMut(JsValue)>::new(move |event: JsValue| {
+            let map = Reflect::get(&event, &"map".into()).unwrap();
+            assert_eq!(Uint8Array::from(map).length(), 12 * 8);
+            completed.set(true);
+        })
+    };
+
+    let mut generator = WfcGenerator::new(&tileset(), config(3)).unwrap();
+    generator.set_onprogress(Some(
+        onprogress.as_ref().unchecked_ref::<Function>().clone(),
+    ));
+    generator.set_oncomplete(Some(
+        oncomplete.as_ref().unchecked_ref::<Function>().clone(),
+    ));
+    assert_eq!(generator.run(None).unwrap(), Status::Done);
+    assert_eq!(progress.get(), (12 * 8) as f64);
+    assert!(completed.get());
+}
+
+#[wasm_bindgen_test]
+async fn generate_resolves_with_the_finished_map() {
+    let promise = generate(&tileset(), config(4)).unwrap();
+    let result = JsFuture::from(promise).await.unwrap();
+    let map = Uint8Array::new(&Reflect::get(&result, &"map".into()).unwrap()).to_vec();
+    let mut generator = WfcGenerator::new(&tileset(), config(4)).unwrap();
+    generator.run(None).unwrap();
+    assert_eq!(map, generator.get_map());
+    assert_eq!(Reflect::get(&result, &"width".into()).unwrap(), 12);
+    assert_eq!(Reflect::get(&result, &"height".into()).unwrap(), 8);
+}
+
+#[wasm_bindgen_test]
+fn generate_rejects_a_time_slice_that_cannot_make_progress() {
+    for time_slice in [0., -5., f64::NAN, f64::INFINITY] {
+        let config = config(4);
+        Reflect::set(&config, &"time_slice".into(), &time_slice.into()).unwrap();
+        let error = generate(&tileset(), config).unwrap_err();
+        assert_eq!(
+            Reflect::get(&error, &"name".into()).unwrap(),
+            "InvalidArgumentError"
+        );
+    }
+}
+
+#[wasm_bindgen_test]
+fn generator_exports_png() {
+    let mut generator = WfcGenerator::new(&tileset(), config(5)).unwrap();
+    generator.run(None).unwrap();
+    let png = generator.to_png(Some(2)).unwrap();
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    // Width and height of the IHDR chunk.
+    assert_eq!(&png[16..24], &[0, 0, 0, 24, 0, 0, 0, 16]);
+}
+
+#[wasm_bindgen_test]
+fn generator_resumes_from_a_snapshot() {
+    let mut generator = WfcGenerator::new(&tileset(), config(9)).unwrap();
+    generator.run(Some(30)).unwrap();
+    let snapshot = generator.snapshot();
+    let mut restored = WfcGenerator::new(&tileset(), JsValue::UNDEFINED).unwrap();
+    restored.restore(&snapshot).unwrap();
+    assert_eq!(restored.snapshot(), snapshot);
+    assert_eq!(restored.run(None).unwrap(), generator.run(None).unwrap());
+    assert_eq!(restored.get_map(), generator.get_map());
+    assert!(restored.restore(&snapshot[..10]).is_err());
+}
+
+#[wasm_bindgen_test]
+fn generator_replays_its_decision_log() {
+    let mut generator = WfcGenerator::new(&tileset(), config(11)).unwrap();
+    generator.run(None).unwrap();
+    let log = generator.decision_log().unwrap();
+    assert!(log.length() > 0);
+    let mut replayed = WfcGenerator::new(&tileset(), config(12)).unwrap();
+    replayed.replay(log.into()).unwrap();
+    replayed.run(None).unwrap();
+    assert_eq!(replayed.get_map(), generator.get_map());
+}
+
+#[wasm_bindgen_test]
+fn log_levels_are_set_by_name_per_category() {
+    set_log_level("trace", Some("propagation".to_string())).unwrap();
+    let mut generator = WfcGenerator::new(&tileset(), config(2)).unwrap();
+    assert_eq!(generator.run(None).unwrap(), Status::Done);
+    set_log_level("off", None).unwrap();
+    set_log_level("warn", Some("render".to_string())).unwrap();
+    let logging = cfg!(feature = "logging");
+    assert!(!log_enabled("debug", "render").unwrap());
+    assert_eq!(log_enabled("warn", "render").unwrap(), logging);
+    assert_eq!(log_enabled("error", "render").unwrap(), logging);
+    // The other categories stay off.
+    assert!(!log_enabled("error", "solver").unwrap());
+    assert!(!log_enabled("error", "propagation").unwrap());
+    set_log_level("off", None).unwrap();
+    assert!(!log_enabled("error", "render").unwrap());
+    assert!(log_enabled("error", "physics").is_err());
+    assert!(set_log_level("loud", None).is_err());
+    assert!(set_log_level("info", Some("physics".to_string())).is_err());
+}
+
+#[wasm_bindgen_test]
+fn pins_hold_through_resets_until_cleared() {
+    let mut generator = WfcGenerator::new(&tileset(), config(13)).unwrap();
+    generator.run(Some(20)).unwrap();
+    generator.pin(5, 4, Some(2)).unwrap();
+    assert_eq!(generator.run(None).unwrap(), Status::Done);
+    assert_eq!(generator.get_map()[4 * 12 + 5], 3);
+    generator.reset();
+    generator.run(None).unwrap();
+    assert_eq!(generator.get_map()[4 * 12 + 5], 3);
+    assert_eq!(generator.pins().iter().filter(|pin| **pin != 0).count(), 1);
+
+    generator.clear_pins().unwrap();
+    assert!(generator.pins().iter().all(|pin| *pin == 0));
+    assert!(generator.pin(12, 0, Some(0)).is_err());
+    assert!(generator.pin(0, 0, Some(4)).is_err());
+}
+
+fn canvas(width: u32, height: u32) -> HtmlCanvasElement {
+    let document = web_sys::window().unwrap().document().unwrap();
+    let canvas: HtmlCanvasElement = document.create_element("canvas").unwrap().unchecked_into();
+    canvas.set_width(width);
+    canvas.set_height(height);
+    canvas
+}
+
+#[wasm_bindgen_test]
+fn webgl_draws_maps_of_odd_widths() {
+    let drawn = canvas(70, 50);
+    // Browsers without WebGL2 would only test the fallback.
+    if drawn.get_context("webgl2").unwrap().is_none() {
+        return;
+    }
+    let config = js_sys::JSON::parse(r#"{"width": 7, "height": 5, "seed": 2}"#).unwrap();
+    let mut generator = WfcGenerator::new(&tileset(), config).unwrap();
+    assert_eq!(generator.run(None).unwrap(), Status::Done);
+    generator.render(drawn.clone().into()).unwrap();
+    let copy = canvas(70, 50);
+    let context: CanvasRenderingContext2d =
+        copy.get_context("2d").unwrap().unwrap().unchecked_into();
+    context
+        .draw_image_with_html_canvas_element(&drawn, 0., 0.)
+        .unwrap();
+    let pixels = context.get_image_data(0., 0., 70., 50.).unwrap().data().0;
+    let expected = generator.rasterize(70, 50).unwrap();
+    // Cell centers, away from where filtering could differ. The map is 50
+    // pixels square, 10 in from the left.
+    for y in 0..5 {
+        for x in 0..7 {
+            let pixel = (10. + (x as f64 + 0.5) * 50. / 7.) as usize;
+            let index = ((y * 10 + 5) * 70 + pixel) * 4;
+            for channel in index..index + 3 {
+                let difference = pixels[channel] as i32 - expected[channel] as i32;
+                assert!(difference.abs() <= 8, "cell ({}, {})", x, y);
+            }
+        }
+    }
+}
+
+#[wasm_bindgen_test]
+fn errors_reach_js_as_named_errors() {
+    let mut generator = WfcGenerator::new(&tileset(), config(1)).unwrap();
+    let error: js_sys::Error = generator.restore(&[1, 2, 3]).unwrap_err().into();
+    assert_eq!(error.name(), "SnapshotError");
+    let error: js_sys::Error = generator.render(JsValue::NULL).unwrap_err().into();
+    assert_eq!(error.name(), "MissingCanvasError");
+    let error: js_sys::Error = generator.set_display_mode("sideways").unwrap_err().into();
+    assert_eq!(error.name(), "InvalidArgumentError");
+    let error: js_sys::Error = set_log_level("loud", None).unwrap_err().into();
+    assert_eq!(error.name(), "InvalidArgumentError");
+}
+
+fn solver() -> Context {
+    let mut context = Context::new(12, 8);
+    context.set_tileset(Tileset::from_image_data(&tileset()).unwrap());
+    context.set_seed(3);
+    context
+}
+
+fn message_type(data: &Object) -> String {
+    Reflect::get(data, &"type".into())
+        .unwrap()
+        .as_string()
+        .unwrap()
+}
+
+#[wasm_bindgen_test]
+fn the_page_follows_the_map_the_worker_streams() {
+    let mut worker = solver();
+    let mut page = Context::new(12, 8);
+    page.map_dirty = false;
+    let mut changed = vec![];
+
+    // A fresh solver sends its whole map first, then only what it writes.
+    let (data, _) = map_message(&mut worker, vec![]).unwrap().unwrap();
+    assert_eq!(message_type(&data), "map");
+    assert!(!receive(&mut page, &data, &mut changed).unwrap());
+    assert!(page.map_dirty);
+    assert!(map_message(&mut worker, vec![]).unwrap().is_none());
+
+    while worker.status == Status::Running {
+        let written: Vec<usize> = worker.step().into_iter().collect();
+        let dirty = worker.map_dirty;
+        if let Some((data, _)) = map_message(&mut worker, written.clone()).unwrap() {
+            let kind = if dirty { "map" } else { "cells" };
+            assert_eq!(message_type(&data), kind);
+            changed.clear();
+            receive(&mut page, &data, &mut changed).unwrap();
+            if !dirty {
+                assert_eq!(changed, written);
+            }
+        }
+        assert_eq!(page.map, worker.map);
+    }
+}
+
+#[wasm_bindgen_test]
+fn cells_out_of_the_map_are_skipped() {
+    let mut page = Context::new(12, 8);
+    let mut changed = vec![];
+    let data = Object::new();
+    Reflect::set(&data, &"type".into(), &"cells".into()).unwrap();
+    // The last, unpaired number is ignored too.
+    let cells = Uint32Array::from(&[0, 3, 96, 2, 5, 1, u32::MAX, 4, 7][..]);
+    Reflect::set(&data, &"cells".into(), &cells).unwrap();
+    receive(&mut page, &data, &mut changed).unwrap();
+    assert_eq!(changed, vec![0, 5]);
+    assert_eq!(page.map[0], 3);
+    assert_eq!(page.map[5], 1);
+    assert_eq!(page.map.iter().filter(|value| **value != 0).count(), 2);
+
+    let data = Object::new();
+    Reflect::set(&data, &"type".into(), &"map".into()).unwrap();
+    Reflect::set(&data, &"map".into(), &Uint8Array::new_with_length(10)).unwrap();
+    let error: js_sys::Error = receive(&mut page, &data, &mut changed).unwrap_err().into();
+    assert_eq!(error.name(), "InvalidArgumentError");
+}
@@ -1,4 +1,45 @@
-import init, {start} from "../pkg/wasm_wfc.js";
+import init, {start, entropy_legend} from "../pkg/wasm_wfc.js";
 await init("../pkg/wasm_wfc_bg.wasm");
 // import {start} from "../pkg/wasm_wfc.js";
-start()
\ No newline at end of file
+// To solve off the main thread pass
+// `worker: new Worker(new URL("./worker.js", import.meta.url), {type: "module"})`.
+// `renderer: "canvas2d"` draws without WebGL2, which is also the fallback when
+// the browser lacks it.
+// `display: "superposition"` or `"domain_size"` shows what undecided cells can
+// still become, also settable later with `app.display_mode = ...`.
+// The wheel zooms, dragging pans and a double click shows the whole map again,
+// `view_controls: false` leaves the canvas events alone.
+// The canvas is sized by the page's CSS and its pixels follow that size at the
+// device pixel ratio, `fit_canvas: false` only matches them once at the start.
+// `pixel_art: true` keeps tile texels sharp instead of smoothing them.
+// `grid: true` draws lines between cells, and `highlight_colors: {grid,
+// contradiction, selected, hovered, last_collapsed}` restyles the outlines,
+// e.g. `{hovered: "#ffffff00"}` to hide the one following the mouse.
+// `transition_duration: 300` fades newly collapsed cells in over 300 ms, or
+// grows them from the middle with `transition: "scale"`.
+// Keys 1 to 4 pick a rotation to pin cells to by clicking them, 0 erases pins
+// and Escape goes back to clicks doing nothing, see `app.brush`.
+const app = start({canvas: "canvas", tileset: "t.png", width: 60, height: 60, steps_per_frame: 10})
+
+// "e" toggles the entropy overlay, with its legend in the corner.
+const legend = document.createElement("div");
+legend.style.cssText = "position: fixed; top: 8px; left: 8px; font: 12px sans-serif; display: none";
+for (const {options, entropy, color} of entropy_legend()) {
+    const row = document.createElement("div");
+    const label = options === 0 ? "contradiction" : `${options} left, ${entropy.toFixed(2)} bits`;
+    row.innerHTML = `<span style="display: inline-block; width: 12px; height: 12px; background: ${color}"></span> ${label}`;
+    legend.append(row);
+}
+document.body.append(legend);
+addEventListener("keydown", (event) => {
+    if (event.key === "e") {
+        app.entropy_overlay = !app.entropy_overlay;
+        legend.style.display = app.entropy_overlay ? "block" : "none";
+    } else if (["1", "2", "3", "4"].includes(event.key)) {
+        app.brush = Number(event.key) - 1;
+    } else if (event.key === "0") {
+        app.brush = "erase";
+    } else if (event.key === "Escape") {
+        app.brush = null;
+    }
+});
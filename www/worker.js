@@ -0,0 +1,3 @@
+import init, {worker_main} from "../pkg/wasm_wfc.js";
+await init("../pkg/wasm_wfc_bg.wasm");
+worker_main();